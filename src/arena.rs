@@ -0,0 +1,487 @@
+//! AVL tree storing its nodes in a single vector
+use super::store::Store;
+use super::{Node, RangeIter};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::iter::FromIterator;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+
+/// The node storage of an [`AVLTreeArena`]: one vector of nodes that link to each other by
+/// index. Slots vacated by removals are chained into a free list and reused by insertions.
+#[derive(Debug, Clone)]
+pub struct Nodes<T> {
+    slots: Vec<Slot<T>>,
+    // the most recently vacated slot
+    free: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Slot<T> {
+    Occupied(Node<T, Option<usize>>),
+    // holds the next vacant slot
+    Vacant(Option<usize>),
+}
+
+impl<T> Nodes<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Nodes {
+            slots: Vec::with_capacity(capacity),
+            free: None,
+        }
+    }
+}
+
+impl<T> Store<T> for Nodes<T> {
+    type Link = Option<usize>;
+
+    fn node<'a>(&'a self, tree: &'a Option<usize>) -> Option<&'a Node<T, Option<usize>>> {
+        match self.slots[(*tree)?] {
+            Slot::Occupied(ref node) => Some(node),
+            Slot::Vacant(_) => unreachable!(),
+        }
+    }
+
+    fn node_mut<'a>(
+        &'a mut self,
+        tree: &'a mut Option<usize>,
+    ) -> Option<&'a mut Node<T, Option<usize>>> {
+        match self.slots[(*tree)?] {
+            Slot::Occupied(ref mut node) => Some(node),
+            Slot::Vacant(_) => unreachable!(),
+        }
+    }
+
+    fn alloc(&mut self, node: Node<T, Option<usize>>) -> Option<usize> {
+        match self.free {
+            None => {
+                self.slots.push(Slot::Occupied(node));
+                Some(self.slots.len() - 1)
+            }
+            Some(i) => {
+                match mem::replace(&mut self.slots[i], Slot::Occupied(node)) {
+                    Slot::Vacant(next) => self.free = next,
+                    Slot::Occupied(_) => unreachable!(),
+                }
+                Some(i)
+            }
+        }
+    }
+
+    fn free(&mut self, tree: Option<usize>) -> Option<Node<T, Option<usize>>> {
+        let i = tree?;
+        match mem::replace(&mut self.slots[i], Slot::Vacant(self.free)) {
+            Slot::Occupied(node) => {
+                self.free = Some(i);
+                Some(node)
+            }
+            Slot::Vacant(_) => unreachable!(),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional);
+    }
+}
+
+/// An AVL tree whose nodes live in one `Vec` and link to each other by index.
+///
+/// Nodes are allocated from the vector, so building a large tree costs a handful of
+/// reallocations instead of one `Box` per value, and none at all with [`with_capacity`] or
+/// [`from_sorted`]. Removed nodes leave vacant slots that later insertions fill first.
+/// Balancing is shared with [`AVLTree`](crate::AVLTree), whose methods these mirror.
+///
+/// [`with_capacity`]: AVLTreeArena::with_capacity
+/// [`from_sorted`]: AVLTreeArena::from_sorted
+#[derive(Debug, Clone)]
+pub struct AVLTreeArena<T> {
+    nodes: Nodes<T>,
+    root: Option<usize>,
+}
+
+/// An iterator over the values of an [`AVLTreeArena`], created by
+/// [`iter`](AVLTreeArena::iter) and [`range`](AVLTreeArena::range).
+pub type Iter<'a, T> = RangeIter<'a, T, Nodes<T>>;
+
+impl<T> Default for AVLTreeArena<T> {
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
+}
+
+impl<T> AVLTreeArena<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty tree with room for `capacity` values before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        AVLTreeArena {
+            nodes: Nodes::with_capacity(capacity),
+            root: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.slots.clear();
+        self.nodes.free = None;
+        self.root = None;
+    }
+
+    /// Returns the number of levels in the tree, 0 for an empty tree.
+    pub fn height(&self) -> usize {
+        self.nodes.height(&self.root)
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.nodes.remove_min(&mut self.root).0
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        self.nodes.remove_max(&mut self.root).0
+    }
+
+    /// Removes and returns the `k`-th smallest value (0-indexed) in O(log n).
+    pub fn pop_nth(&mut self, k: usize) -> Option<T> {
+        if k >= self.len() {
+            return None;
+        }
+        self.nodes.remove_nth(&mut self.root, k).0
+    }
+
+    fn drain_sorted(&mut self) -> Vec<T> {
+        // empties the tree, releasing every slot
+        let mut values = Vec::with_capacity(self.len());
+        self.nodes.drain_sorted(self.root.take(), &mut values);
+        self.clear();
+        values
+    }
+}
+
+impl<T: Ord> AVLTreeArena<T> {
+    /// Builds a balanced tree from strictly increasing values with a single allocation.
+    pub fn from_sorted(data: Vec<T>) -> Self {
+        debug_assert!(data.windows(2).all(|w| w[0] < w[1]));
+        Self::from_sorted_iter(data.into_iter())
+    }
+
+    /// Builds a balanced tree from a strictly increasing iterator of known length.
+    pub fn from_sorted_iter<I: ExactSizeIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.root = tree.nodes.build(iter);
+        debug_assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| a < b));
+        tree
+    }
+
+    pub fn insert(&mut self, value: T) -> bool {
+        self.nodes.add_by(&mut self.root, value, &T::cmp)
+    }
+
+    /// Removes and returns the value equal to `value`, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes
+            .remove_by(&mut self.root, &|v: &T| value.cmp(v.borrow()))
+            .0
+    }
+
+    /// Moves the values `>= key` into a new tree and returns it.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let (left, right) = self.nodes.split(self.root.take(), key);
+        self.root = left;
+        let mut values = Vec::with_capacity(self.nodes.len(&right));
+        self.nodes.drain_sorted(right, &mut values);
+        Self::from_sorted(values)
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        let other = other.drain_sorted();
+        let other = self.nodes.build(other.into_iter());
+        self.nodes.append(&mut self.root, other);
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        // rebuilding from scratch also compacts the vector
+        let mut values = self.drain_sorted();
+        values.retain(|v| f(v));
+        self.root = self.nodes.build(values.into_iter());
+    }
+
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.get(&self.root, value)
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.get(value).is_some()
+    }
+
+    pub fn floor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.floor(&self.root, key)
+    }
+
+    pub fn ceiling<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.ceiling(&self.root, key)
+    }
+
+    pub fn predecessor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.predecessor(&self.root, key)
+    }
+
+    pub fn successor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.successor(&self.root, key)
+    }
+
+    /// Returns the number of values strictly less than `key`.
+    pub fn rank<Q>(&self, key: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.nodes.rank(&self.root, key)
+    }
+
+    /// Returns the `k`-th smallest value, counting from zero.
+    pub fn select(&self, k: usize) -> Option<&T> {
+        self.nodes.select(&self.root, k)
+    }
+
+    /// Returns the smallest value.
+    pub fn first(&self) -> Option<&T> {
+        self.nodes.first(&self.root)
+    }
+
+    /// Returns the largest value.
+    pub fn last(&self) -> Option<&T> {
+        self.nodes.last(&self.root)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.range_bounds::<T, _>(..)
+    }
+
+    /// Returns an iterator over the half-open range `[l, r)`.
+    ///
+    /// `None` leaves that side unbounded; if `l > r` the iterator yields nothing.
+    pub fn range<K>(&self, l: Option<&K>, r: Option<&K>) -> Iter<'_, T>
+    where
+        T: Borrow<K>,
+        K: ?Sized + Ord,
+    {
+        let start = l.map_or(Bound::Unbounded, Bound::Included);
+        let end = r.map_or(Bound::Unbounded, Bound::Excluded);
+        RangeIter::new(&self.nodes, &self.root, start, end)
+    }
+
+    pub fn range_bounds<K, R>(&self, range: R) -> Iter<'_, T>
+    where
+        T: Borrow<K>,
+        K: ?Sized + Ord,
+        R: RangeBounds<K>,
+    {
+        RangeIter::new(
+            &self.nodes,
+            &self.root,
+            range.start_bound(),
+            range.end_bound(),
+        )
+    }
+
+    /// Verifies the ordering of the values, the balance factors and the subtree sizes.
+    pub fn check_invariant(&self) -> bool {
+        self.iter().zip(self.iter().skip(1)).all(|(a, b)| a < b)
+            && self.nodes.checked_height(&self.root).is_some()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLTreeArena<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTreeArena<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut tree = Self::with_capacity(iter.size_hint().0);
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord> Extend<T> for AVLTreeArena<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for v in iter {
+            self.insert(v);
+        }
+    }
+}
+
+impl<T: Ord> PartialEq for AVLTreeArena<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Ord> Eq for AVLTreeArena<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+    use std::collections::HashSet;
+
+    #[quickcheck]
+    fn insert_and_get(v: Vec<u16>, keys: Vec<u16>) -> bool {
+        let mut tree = AVLTreeArena::new();
+        let mut expected = HashSet::new();
+        v.iter().all(|&x| tree.insert(x) == expected.insert(x))
+            && tree.len() == expected.len()
+            && keys
+                .iter()
+                .all(|k| tree.contains(k) == expected.contains(k))
+            && tree.check_invariant()
+    }
+
+    #[quickcheck]
+    fn matches_avltree(v: HashSet<usize>, l: Option<usize>, r: Option<usize>, k: usize) -> bool {
+        let arena: AVLTreeArena<_> = v.iter().copied().collect();
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let k = k % (v.len() + 1);
+        arena.iter().eq(tree.iter())
+            && arena.iter().rev().eq(tree.iter().rev())
+            && arena
+                .range(l.as_ref(), r.as_ref())
+                .eq(tree.range(l.as_ref(), r.as_ref()))
+            && arena.range(l.as_ref(), r.as_ref()).len()
+                == tree.range(l.as_ref(), r.as_ref()).count()
+            && arena.select(k) == tree.select(k)
+            && l.iter().all(|l| {
+                arena.rank(l) == tree.rank(l)
+                    && arena.floor(l) == tree.floor(l)
+                    && arena.ceiling(l) == tree.ceiling(l)
+                    && arena.predecessor(l) == tree.predecessor(l)
+                    && arena.successor(l) == tree.successor(l)
+            })
+            && arena.first() == tree.first()
+            && arena.last() == tree.last()
+            && arena.height() == tree.height()
+    }
+
+    #[quickcheck]
+    fn remove_matches_avltree(v: Vec<u8>, ops: Vec<(u8, u8)>) -> bool {
+        let mut arena: AVLTreeArena<_> = v.iter().copied().collect();
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        for &(op, x) in ops.iter() {
+            let same = match op % 5 {
+                0 => arena.insert(x) == tree.insert(x),
+                1 => arena.take(&x) == tree.take(&x),
+                2 => arena.pop_min() == tree.pop_min(),
+                3 => arena.pop_max() == tree.pop_max(),
+                _ => arena.pop_nth(x as usize) == tree.pop_nth(x as usize),
+            };
+            if !same || !arena.check_invariant() {
+                return false;
+            }
+        }
+        arena.iter().eq(tree.iter()) && arena.height() == tree.height()
+    }
+
+    #[quickcheck]
+    fn split_append_retain(v: HashSet<u16>, w: HashSet<u16>, key: u16) -> bool {
+        let mut arena: AVLTreeArena<_> = v.iter().copied().collect();
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let right = arena.split_off(&key);
+        let right_tree = tree.split_off(&key);
+        let split = arena.iter().eq(tree.iter()) && right.iter().eq(right_tree.iter());
+        arena.append(&mut w.iter().copied().collect());
+        tree.append(&mut w.iter().copied().collect());
+        let appended = arena.iter().eq(tree.iter());
+        arena.retain(|x| x % 3 != 0);
+        tree.retain(|x| x % 3 != 0);
+        split
+            && appended
+            && arena.iter().eq(tree.iter())
+            && arena.check_invariant()
+            && right.check_invariant()
+    }
+
+    #[test]
+    fn reuses_vacant_slots() {
+        let mut tree: AVLTreeArena<_> = (0..100).collect();
+        for x in (0..100).step_by(2) {
+            assert_eq!(tree.take(&x), Some(x));
+        }
+        for x in 100..150 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.nodes.slots.len(), 100);
+        assert!(tree.check_invariant());
+    }
+
+    #[quickcheck]
+    fn from_sorted(v: HashSet<usize>) -> bool {
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        let tree = AVLTreeArena::from_sorted(w.clone());
+        tree.iter().eq(w.iter()) && tree.check_invariant()
+    }
+
+    #[test]
+    fn bulk_build_100k() {
+        let n = 100_000;
+        let tree = AVLTreeArena::from_sorted_iter(0..n);
+        assert_eq!(tree.len(), n);
+        assert_eq!(tree.nodes.slots.capacity(), n);
+        assert!(tree.check_invariant());
+        let mut tree = AVLTreeArena::with_capacity(n);
+        for x in (0..n).rev() {
+            tree.insert(x);
+        }
+        assert_eq!(tree.len(), n);
+        assert_eq!(tree.nodes.slots.capacity(), n);
+        assert!(tree.iter().copied().eq(0..n));
+        assert!(tree.check_invariant());
+    }
+}
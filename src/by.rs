@@ -0,0 +1,153 @@
+//! AVL tree ordered by a user-supplied comparator
+use super::store::Boxed;
+use super::{AVLTree, RangeIter};
+use core::cmp::Ordering;
+use core::ops::{Bound, RangeBounds};
+
+/// A sorted set ordered by a comparator `cmp` instead of `T`'s `Ord` impl.
+///
+/// `cmp` must be a total order and must give the same answers for the whole lifetime of the
+/// tree; otherwise lookups, insertions and removals may behave incorrectly.
+pub struct AVLTreeBy<T, F> {
+    tree: AVLTree<T>,
+    cmp: F,
+}
+
+impl<T, F> AVLTreeBy<T, F>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    /// Creates an empty tree ordered by `cmp`.
+    pub fn new(cmp: F) -> Self {
+        AVLTreeBy {
+            tree: AVLTree::Empty,
+            cmp,
+        }
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns whether the tree holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Inserts `value`, returning `false` if an equal value under `cmp` is already present.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.tree.add_by(value, &self.cmp)
+    }
+
+    /// Returns the stored value equal to `value` under `cmp`.
+    pub fn get(&self, value: &T) -> Option<&T> {
+        self.tree.find_by(&|v: &T| (self.cmp)(value, v))
+    }
+
+    /// Returns whether a value equal to `value` under `cmp` is present.
+    pub fn contains(&self, value: &T) -> bool {
+        self.get(value).is_some()
+    }
+
+    /// Removes and returns the value equal to `value` under `cmp`, if any.
+    pub fn remove(&mut self, value: &T) -> Option<T> {
+        let cmp = &self.cmp;
+        self.tree.remove_by(&|v: &T| cmp(value, v)).0
+    }
+
+    /// Removes and returns the first value in `cmp` order.
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.tree.pop_min()
+    }
+
+    /// Removes and returns the last value in `cmp` order.
+    pub fn pop_max(&mut self) -> Option<T> {
+        self.tree.pop_max()
+    }
+
+    /// Returns an iterator over the values in `cmp` order.
+    pub fn iter(&self) -> RangeIter<'_, T> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over the values within `range`, with bounds compared by `cmp`.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> RangeIter<'_, T> {
+        let cmp = &self.cmp;
+        let after_start = |v: &T| match range.start_bound() {
+            Bound::Unbounded => true,
+            Bound::Included(k) => cmp(k, v) != Ordering::Greater,
+            Bound::Excluded(k) => cmp(k, v) == Ordering::Less,
+        };
+        let before_end = |v: &T| match range.end_bound() {
+            Bound::Unbounded => true,
+            Bound::Included(k) => cmp(v, k) != Ordering::Greater,
+            Bound::Excluded(k) => cmp(v, k) == Ordering::Less,
+        };
+        RangeIter::new_by(&Boxed, &self.tree, after_start, before_end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{check_factors, check_height};
+    use std::collections::HashSet;
+
+    #[quickcheck]
+    fn reversed(v: Vec<usize>) -> bool {
+        let mut tree = AVLTreeBy::new(|a: &usize, b: &usize| b.cmp(a));
+        for &x in v.iter() {
+            tree.insert(x);
+        }
+        let mut w: Vec<_> = v
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        w.sort();
+        w.reverse();
+        tree.len() == w.len()
+            && tree.iter().copied().eq(w.iter().copied())
+            && v.iter().all(|x| tree.contains(x))
+            && check_factors(&tree.tree)
+            && check_height(tree.tree)
+    }
+
+    #[quickcheck]
+    fn range(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let tree = {
+            let mut tree = AVLTreeBy::new(|a: &usize, b: &usize| b.cmp(a));
+            for &x in v.iter() {
+                tree.insert(x);
+            }
+            tree
+        };
+        let (hi, lo) = if l < r { (r, l) } else { (l, r) };
+        let mut w: Vec<_> = v.iter().copied().filter(|&x| lo < x && x <= hi).collect();
+        w.sort();
+        w.reverse();
+        tree.range(hi..lo).copied().eq(w.iter().copied())
+            && tree
+                .range(hi..lo)
+                .rev()
+                .copied()
+                .eq(w.iter().rev().copied())
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let mut tree = AVLTreeBy::new(|a: &&str, b: &&str| a.to_lowercase().cmp(&b.to_lowercase()));
+        assert!(tree.insert("Banana"));
+        assert!(tree.insert("apple"));
+        assert!(!tree.insert("APPLE"));
+        assert_eq!(tree.get(&"BANANA"), Some(&"Banana"));
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec!["apple", "Banana"]
+        );
+        assert_eq!(tree.remove(&"Apple"), Some("apple"));
+        assert_eq!(tree.len(), 1);
+    }
+}
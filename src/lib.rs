@@ -1,500 +1,3663 @@
-/// Implementation of AVL tree
-#[allow(clippy::redundant_field_names)]
+#![cfg_attr(not(test), no_std)]
+//! Implementation of AVL tree
+extern crate alloc;
 #[cfg(test)]
 extern crate quickcheck;
 #[cfg(test)]
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
-use std::borrow::Borrow;
-use std::cmp::Ordering::*;
-use std::default::Default;
-use std::iter::{FromIterator, Iterator};
-use std::mem;
-use std::ops::Index;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering::*;
+use core::default::Default;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::{FromIterator, FusedIterator, Iterator};
+use core::mem;
+use core::ops::{Bound, Index, RangeBounds};
 
-#[derive(Debug)]
+pub mod arena;
+mod by;
+pub mod map;
+pub mod multiset;
+mod store;
+pub use arena::AVLTreeArena;
+pub use by::AVLTreeBy;
+pub use map::AVLMap;
+pub use multiset::AVLMultiSet;
+use store::{Boxed, Store};
+
+#[derive(Debug, Default)]
 pub enum AVLTree<T> {
+    #[default]
     Empty,
     NonEmpty(Box<Node<T>>),
 }
 use AVLTree::*;
 
+/// A tree node; `L` links it to its subtrees, which are themselves [`AVLTree`]s by default.
 #[derive(Debug)]
-pub struct Node<T> {
+pub struct Node<T, L = AVLTree<T>> {
     pub value: T,
-    pub left: AVLTree<T>,
-    pub right: AVLTree<T>,
+    pub left: L,
+    pub right: L,
     balance_factor: i8,
+    size: usize,
 }
 
-impl<T> Default for AVLTree<T> {
-    fn default() -> Self {
-        Empty
+impl<T, L> Node<T, L> {
+    /// Returns the height of the right subtree minus that of the left; `-1`, `0` or `1` in a valid tree.
+    pub fn balance_factor(&self) -> i8 {
+        self.balance_factor
     }
 }
 
-impl<T> AVLTree<T>
-where
-    T: Ord,
-{
-    pub fn insert(&mut self, value: T) -> bool {
-        self.add(value).0
-    }
-
-    fn add(&mut self, value: T) -> (bool, bool) {
-        // returns: (inserted, deepened)
-        let ret = match *self {
-            Empty => {
-                let node = Node {
-                    value: value,
-                    left: Empty,
-                    right: Empty,
-                    balance_factor: 0,
-                };
-                *self = NonEmpty(Box::new(node));
-                (true, true)
-            }
-            NonEmpty(ref mut node) => match node.value.cmp(&value) {
-                Equal => (false, false),
-                Less => {
-                    let (inserted, deepened) = node.right.add(value);
-                    if deepened {
-                        let ret = match node.balance_factor {
-                            -1 => (inserted, false),
-                            0 => (inserted, true),
-                            1 => (inserted, false),
-                            _ => unreachable!(),
-                        };
-                        node.balance_factor += 1;
-                        ret
-                    } else {
-                        (inserted, deepened)
-                    }
-                }
-                Greater => {
-                    let (inserted, deepened) = node.left.add(value);
-                    if deepened {
-                        let ret = match node.balance_factor {
-                            -1 => (inserted, false),
-                            0 => (inserted, true),
-                            1 => (inserted, false),
-                            _ => unreachable!(),
-                        };
-                        node.balance_factor -= 1;
-                        ret
-                    } else {
-                        (inserted, deepened)
-                    }
-                }
-            },
-        };
-        self.balance();
-        ret
+impl<T> AVLTree<T> {
+    /// Creates an empty tree.
+    pub const fn new() -> Self {
+        Empty
     }
 
-    fn balance(&mut self) {
+    /// Returns the number of values, read from the root's cached subtree size in O(1).
+    pub fn len(&self) -> usize {
         match *self {
-            Empty => (),
-            NonEmpty(_) => match self.node().balance_factor {
-                -2 => {
-                    let lf = self.node().left.node().balance_factor;
-                    if lf == -1 || lf == 0 {
-                        let (a, b) = if lf == -1 { (0, 0) } else { (-1, 1) };
-                        self.rotate_right();
-                        self.node().right.node().balance_factor = a;
-                        self.node().balance_factor = b;
-                    } else if lf == 1 {
-                        let (a, b) = match self.node().left.node().right.node().balance_factor {
-                            -1 => (1, 0),
-                            0 => (0, 0),
-                            1 => (0, -1),
-                            _ => unreachable!(),
-                        };
-                        self.node().left.rotate_left();
-                        self.rotate_right();
-                        self.node().right.node().balance_factor = a;
-                        self.node().left.node().balance_factor = b;
-                        self.node().balance_factor = 0;
-                    } else {
-                        unreachable!()
-                    }
-                }
-                2 => {
-                    let lf = self.node().right.node().balance_factor;
-                    if lf == 1 || lf == 0 {
-                        let (a, b) = if lf == 1 { (0, 0) } else { (1, -1) };
-                        self.rotate_left();
-                        self.node().left.node().balance_factor = a;
-                        self.node().balance_factor = b;
-                    } else if lf == -1 {
-                        let (a, b) = match self.node().right.node().left.node().balance_factor {
-                            1 => (-1, 0),
-                            0 => (0, 0),
-                            -1 => (0, 1),
-                            _ => unreachable!(),
-                        };
-                        self.node().right.rotate_right();
-                        self.rotate_left();
-                        self.node().left.node().balance_factor = a;
-                        self.node().right.node().balance_factor = b;
-                        self.node().balance_factor = 0;
-                    } else {
-                        unreachable!()
-                    }
-                }
-                _ => (),
-            },
+            Empty => 0,
+            NonEmpty(ref v) => v.size,
         }
     }
+    pub fn is_empty(&self) -> bool {
+        matches!(*self, Empty)
+    }
 
-    fn node(&mut self) -> &mut Node<T> {
+    /// Returns the root node, or `None` for an empty tree.
+    pub fn as_node(&self) -> Option<&Node<T>> {
         match *self {
-            Empty => panic!("call on empty tree"),
-            NonEmpty(ref mut v) => v,
+            Empty => None,
+            NonEmpty(ref node) => Some(node),
         }
     }
 
-    fn right(&mut self) -> &mut Self {
-        match *self {
-            Empty => panic!("call on empty tree"),
-            NonEmpty(ref mut node) => &mut node.right,
+    /// Returns an iterator yielding mutable references to the values in sorted order.
+    ///
+    /// Mutating a value in a way that changes its ordering relative to the other
+    /// values is a logic error: the tree is not rebalanced or reordered, so later
+    /// lookups and insertions may behave incorrectly.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut::new(self)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Empty;
+    }
+
+    /// Removes all values, returning them in sorted order and leaving `self` empty.
+    pub fn drain(&mut self) -> IntoIter<T> {
+        mem::take(self).into_iter()
+    }
+
+    /// Renders the tree sideways: the right subtree above each node, the left below,
+    /// indented by depth. Each node is shown as `value (balance_factor)`.
+    pub fn debug_tree(&self) -> String
+    where
+        T: fmt::Debug,
+    {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String)
+    where
+        T: fmt::Debug,
+    {
+        if let NonEmpty(ref node) = *self {
+            node.right.write_tree(depth + 1, out);
+            out.push_str(&format!(
+                "{}{:?} ({})\n",
+                "    ".repeat(depth),
+                node.value,
+                node.balance_factor
+            ));
+            node.left.write_tree(depth + 1, out);
         }
     }
 
-    fn left(&mut self) -> &mut Self {
-        match *self {
-            Empty => panic!("call on empty tree"),
-            NonEmpty(ref mut node) => &mut node.left,
+    fn values(&self) -> Values<'_, T> {
+        Values::new(self)
+    }
+
+    fn into_node(self) -> Option<Box<Node<T>>> {
+        // moves the root node out without running `Drop` on `self`
+        let tree = mem::ManuallyDrop::new(self);
+        match *tree {
+            Empty => None,
+            // SAFETY: `tree` is never dropped, so the box has exactly one owner
+            NonEmpty(ref node) => Some(unsafe { core::ptr::read(node) }),
+        }
+    }
+
+    fn add_by<C>(&mut self, value: T, cmp: &C) -> bool
+    where
+        C: Fn(&T, &T) -> core::cmp::Ordering,
+    {
+        Boxed.add_by(self, value, cmp)
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        Boxed.remove_min(self).0
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        Boxed.remove_max(self).0
+    }
+
+    /// Removes and returns the `k`-th smallest value (0-indexed) in O(log n).
+    pub fn pop_nth(&mut self, k: usize) -> Option<T> {
+        if k >= self.len() {
+            return None;
         }
+        Boxed.remove_nth(self, k).0
+    }
+
+    /// Removes and returns the value at rank `k`; an alias of [`pop_nth`](AVLTree::pop_nth)
+    /// that pairs with [`position`](AVLTree::position).
+    pub fn remove_rank(&mut self, k: usize) -> Option<T> {
+        self.pop_nth(k)
     }
 
-    fn rotate_right(&mut self) {
-        let mut v = mem::replace(self, Empty);
-        let mut left = mem::replace(v.left(), Empty);
-        let left_right = mem::replace(left.right(), Empty);
-        *v.left() = left_right;
-        *left.right() = v;
+    /// Keeps the `k` smallest values in `self` and returns the rest, in O(log n).
+    pub fn split_at_rank(&mut self, k: usize) -> Self {
+        let (left, right) = Boxed.split_rank(mem::take(self), k);
         *self = left;
+        right
     }
 
-    fn rotate_left(&mut self) {
-        let mut v = mem::replace(self, Empty);
-        let mut right = mem::replace(v.right(), Empty);
-        let right_left = mem::replace(right.left(), Empty);
-        *v.right() = right_left;
-        *right.left() = v;
-        *self = right;
+    fn remove_by<F>(&mut self, f: &F) -> (Option<T>, bool)
+    where
+        F: Fn(&T) -> core::cmp::Ordering,
+    {
+        Boxed.remove_by(self, f)
     }
 
-    #[cfg(test)]
-    fn depth(&self) -> usize {
+    /// Returns the number of levels in the tree, 0 for an empty tree.
+    ///
+    /// Runs in O(log n) by following the taller child at each node.
+    pub fn height(&self) -> usize {
+        Boxed.height(self)
+    }
+
+    /// Rebuilds the tree into a perfectly balanced shape of minimum height in O(n).
+    ///
+    /// Useful before a long read-heavy phase; later insertions and removals keep it AVL-balanced.
+    pub fn rebalance_optimal(&mut self) {
+        *self = Boxed.build(mem::take(self).into_iter());
+    }
+
+    /// Recomputes every node's balance factor and cached subtree size, without rotating.
+    ///
+    /// Needed after moving subtrees through the public [`left`](Node::left) and
+    /// [`right`](Node::right) fields, e.g. grafting a subtree of one tree onto a leaf of
+    /// another. The result is only a valid AVL tree if the new shape is ordered and
+    /// balanced; [`check_invariant`](AVLTree::check_invariant) tells whether it is.
+    pub fn recompute_balance_factors(&mut self) {
+        self.recompute_heights();
+    }
+
+    fn recompute_heights(&mut self) -> usize {
+        // returns: height
         match *self {
             Empty => 0,
-            NonEmpty(ref v) => std::cmp::max(v.left.depth(), v.right.depth()) + 1,
+            NonEmpty(ref mut node) => {
+                let hl = node.left.recompute_heights() as isize;
+                let hr = node.right.recompute_heights() as isize;
+                node.balance_factor = (hr - hl).clamp(-128, 127) as i8;
+                node.size = 1 + node.left.len() + node.right.len();
+                hl.max(hr) as usize + 1
+            }
         }
     }
 
-    pub fn len(&self) -> usize {
+    #[cfg(test)]
+    fn node(&mut self) -> &mut Node<T> {
         match *self {
-            Empty => 0,
-            NonEmpty(ref v) => 1 + v.left.len() + v.right.len(),
+            Empty => panic!("call on empty tree"),
+            NonEmpty(ref mut v) => v,
         }
     }
-    pub fn is_empty(&self) -> bool {
+
+    #[cfg(test)]
+    fn right(&mut self) -> &mut Self {
         match *self {
-            Empty => true,
-            _ => false,
+            Empty => panic!("call on empty tree"),
+            NonEmpty(ref mut node) => &mut node.right,
         }
     }
 
-    pub fn iter<'a>(&'a self) -> RangeIter<'a, T, &T> {
-        self.range(None, None)
+    #[cfg(test)]
+    fn left(&mut self) -> &mut Self {
+        match *self {
+            Empty => panic!("call on empty tree"),
+            NonEmpty(ref mut node) => &mut node.left,
+        }
     }
 
-    pub fn range<'a, 'b, K>(&'a self, l: Option<&'b K>, r: Option<&'b K>) -> RangeIter<'a, T, &'b K>
+    fn find_by<F>(&self, f: &F) -> Option<&T>
     where
-        T: Borrow<K>,
-        K: ?Sized + Ord,
+        F: Fn(&T) -> core::cmp::Ordering,
     {
-        RangeIter::new(self, l, r)
+        Boxed.find_by(self, f)
     }
 
-    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    fn find_by_mut<F>(&mut self, f: &F) -> Option<&mut T>
     where
-        T: Borrow<Q>,
-        Q: ?Sized + Ord,
+        F: Fn(&T) -> core::cmp::Ordering,
     {
         match *self {
             Empty => None,
-            NonEmpty(ref node) => match value.cmp(node.value.borrow()) {
-                Less => node.left.get(value),
-                Equal => Some(&node.value),
-                Greater => node.right.get(value),
+            NonEmpty(ref mut node) => match f(&node.value) {
+                Less => node.left.find_by_mut(f),
+                Equal => Some(&mut node.value),
+                Greater => node.right.find_by_mut(f),
             },
         }
     }
+}
 
-    #[cfg(test)]
-    fn value(&self) -> Option<&T> {
-        match *self {
-            Empty => None,
-            NonEmpty(ref v) => Some(&v.value),
-        }
+impl<T> AVLTree<T>
+where
+    T: Ord,
+{
+    /// Builds a balanced tree from strictly increasing values in O(n).
+    pub fn from_sorted(data: Vec<T>) -> Self {
+        debug_assert!(data.windows(2).all(|w| w[0] < w[1]));
+        Boxed.build(data.into_iter())
     }
-}
 
-pub struct IntoIter<T> {
-    stack: Vec<Node<T>>,
-}
+    /// Like [`from_sorted`](AVLTree::from_sorted), but checks the order first.
+    ///
+    /// Returns `Err(i)` if `data[i]` is not greater than `data[i - 1]`, for the first such `i`.
+    pub fn try_from_sorted(data: Vec<T>) -> Result<Self, usize> {
+        match data.windows(2).position(|w| w[0] >= w[1]) {
+            Some(i) => Err(i + 1),
+            None => Ok(Self::from_sorted(data)),
+        }
+    }
 
-impl<T> IntoIter<T> {
-    pub fn new(tree: AVLTree<T>) -> Self {
-        let mut into_iter = IntoIter { stack: Vec::new() };
-        into_iter.traverse_left(tree);
-        into_iter
+    /// Builds a balanced tree from a strictly increasing iterator of known length in O(n).
+    pub fn from_sorted_iter<I: ExactSizeIterator<Item = T>>(iter: I) -> Self {
+        let tree = Boxed.build(iter);
+        debug_assert!(tree.values().zip(tree.values().skip(1)).all(|(a, b)| a < b));
+        tree
     }
 
-    fn traverse_left(&mut self, mut tree: AVLTree<T>) {
-        while let NonEmpty(mut node) = tree {
-            tree = mem::replace(&mut node.left, Empty);
-            self.stack.push(*node);
+    /// Builds a tree from `iter`, failing on the first value that is already present.
+    pub fn from_iter_checked<I: IntoIterator<Item = T>>(
+        iter: I,
+    ) -> Result<Self, DuplicateError<T>> {
+        let mut tree = Empty;
+        for v in iter {
+            if tree.contains(&v) {
+                return Err(DuplicateError(v));
+            }
+            tree.insert(v);
         }
+        Ok(tree)
     }
-}
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut node = match self.stack.pop() {
-            None => return None,
-            Some(node) => node,
-        };
-        self.traverse_left(mem::replace(&mut node.right, Empty));
-        Some(node.value)
+    pub fn insert(&mut self, value: T) -> bool {
+        self.add(value)
     }
-}
 
-impl<T> IntoIterator for AVLTree<T> {
-    type Item = T;
-    type IntoIter = IntoIter<T>;
-    fn into_iter(self) -> Self::IntoIter {
-        IntoIter::new(self)
+    /// An alias of [`replace`](AVLTree::replace), named to pair with [`insert`](AVLTree::insert).
+    pub fn insert_replace(&mut self, value: T) -> Option<T> {
+        self.replace(value)
     }
-}
 
-impl<T: Ord> FromIterator<T> for AVLTree<T> {
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut tree = Empty;
-        for v in iter {
-            tree.insert(v);
+    /// Inserts `value`, returning the equal value it displaced, if any.
+    ///
+    /// Unlike [`insert`](AVLTree::insert), the stored value is always the new one.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        if self.contains(&value) {
+            let old = self.get_mut(&value).unwrap();
+            Some(mem::replace(old, value))
+        } else {
+            self.insert(value);
+            None
         }
-        tree
     }
-}
 
-impl<T: Ord> Index<&T> for AVLTree<T> {
-    type Output = T;
-    fn index(&self, index: &T) -> &Self::Output {
-        self.get(index).unwrap()
+    fn add(&mut self, value: T) -> bool {
+        self.add_by(value, &T::cmp)
     }
-}
 
-pub struct RangeIter<'a, T, K> {
-    end: Option<K>,
-    stack: Vec<&'a Node<T>>,
-}
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let (left, right) = Boxed.split(mem::take(self), key);
+        *self = left;
+        right
+    }
 
-impl<'a, 'b, T, K> RangeIter<'a, T, &'b K>
-where
-    T: Ord + Borrow<K>,
-    K: ?Sized + Ord,
-{
-    fn new(tree: &'a AVLTree<T>, start: Option<&'b K>, end: Option<&'b K>) -> Self {
-        let mut iter = RangeIter {
-            end: end,
-            stack: Vec::new(),
+    /// Removes every value in the half-open range `[l, r)` and returns how many were removed.
+    pub fn remove_range<Q>(&mut self, l: Option<&Q>, r: Option<&Q>) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let mut right = match r {
+            Some(r) => self.split_off(r),
+            None => Empty,
         };
-        match start {
-            None => iter.traverse_left(tree),
-            Some(i) => iter.traverse(tree, i),
-        }
-        iter
+        let removed = match l {
+            Some(l) => self.split_off(l),
+            None => mem::replace(self, Empty),
+        };
+        self.append(&mut right);
+        removed.len()
     }
-    fn traverse_left(&mut self, mut tree: &'a AVLTree<T>) {
-        while let NonEmpty(ref node) = tree {
-            self.stack.push(node);
-            tree = &node.left;
-        }
+
+    /// Inserts many values at once by sorting them and merging with the tree in O(n + m log m).
+    ///
+    /// As with [`insert`](AVLTree::insert), values already present are kept.
+    pub fn bulk_insert<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut values: Vec<_> = iter.into_iter().collect();
+        values.sort();
+        values.dedup();
+        self.append(&mut Self::from_sorted(values));
     }
-    fn traverse(&mut self, tree: &'a AVLTree<T>, start: &K) {
-        match *tree {
-            Empty => (),
-            NonEmpty(ref node) => match start.cmp(node.value.borrow()) {
-                Less => {
-                    self.stack.push(&node);
-                    self.traverse(&node.left, start);
-                }
-                Equal => self.stack.push(&node),
-                Greater => {
-                    self.traverse(&node.right, start);
-                }
-            },
+
+    /// Inserts every value from `iter`, returning how many were not already present.
+    pub fn merge_from<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        let mut added = 0;
+        for v in iter {
+            if self.insert(v) {
+                added += 1;
+            }
         }
+        added
     }
-}
 
-impl<'a, 'b, T, K> Iterator for RangeIter<'a, T, &'b K>
-where
-    T: Ord + Borrow<K>,
-    K: ?Sized + Ord,
-{
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.stack.pop() {
-            None => None,
-            Some(ref node) => match self.end {
-                None => {
-                    self.traverse_left(&node.right);
-                    Some(&node.value)
-                }
-                Some(r) => match r.cmp(&node.value.borrow()) {
-                    Greater => {
-                        self.traverse_left(&node.right);
-                        Some(&node.value)
+    /// Concatenates two trees in O(log n), given every value in `self` is less than every
+    /// value in `other`.
+    pub fn join(self, other: Self) -> Self {
+        debug_assert!(self.is_empty() || other.is_empty() || self.last() < other.first());
+        Boxed.join(self, other)
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        Boxed.append(self, mem::take(other));
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        Boxed.retain(self, f);
+    }
+
+    /// Removes every value matching `pred`, calling it once per value.
+    ///
+    /// If more than half of the values match, the survivors are rebuilt into a fresh tree in
+    /// O(n); otherwise the matches are deleted one by one in O(m log n) for `m` matches.
+    pub fn remove_if<F: FnMut(&T) -> bool>(&mut self, mut pred: F) {
+        let ranks: Vec<_> = self
+            .iter()
+            .enumerate()
+            .filter(|&(_, v)| pred(v))
+            .map(|(i, _)| i)
+            .collect();
+        if ranks.len() * 2 > self.len() {
+            let mut ranks = ranks.into_iter().peekable();
+            let values: Vec<_> = mem::replace(self, Empty)
+                .into_iter()
+                .enumerate()
+                .filter(|&(i, _)| {
+                    let matched = ranks.peek() == Some(&i);
+                    if matched {
+                        ranks.next();
                     }
-                    _ => None,
-                },
-            },
+                    !matched
+                })
+                .map(|(_, v)| v)
+                .collect();
+            *self = Self::from_sorted(values);
+        } else {
+            // removing from the back keeps the remaining ranks valid
+            for &k in ranks.iter().rev() {
+                self.pop_nth(k);
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashSet;
+    /// Like [`retain`](AVLTree::retain), but returns the number of values removed.
+    pub fn retain_count<F: FnMut(&T) -> bool>(&mut self, f: F) -> usize {
+        let before = self.len();
+        self.retain(f);
+        before - self.len()
+    }
 
-    #[test]
-    fn rotate_right() {
-        let mut tree = AVLTree::Empty;
-        tree.add(3usize);
-        tree.add(4usize);
-        tree.add(1usize);
-        tree.add(2usize);
-        tree.add(0usize);
-        assert_eq!(tree.left().value().unwrap(), &1);
-        assert_eq!(tree.left().left().value().unwrap(), &0);
-        tree.rotate_right();
-        assert_eq!(tree.value().unwrap(), &1);
-        assert_eq!(tree.left().value().unwrap(), &0);
-        assert_eq!(tree.right().value().unwrap(), &3);
-        assert_eq!(tree.right().left().value().unwrap(), &2);
+    /// Removes the values matching `pred` and returns them in sorted order.
+    pub fn extract_if<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> impl Iterator<Item = T> {
+        let (extracted, kept): (Vec<_>, Vec<_>) =
+            mem::replace(self, Empty).into_iter().partition(|v| pred(v));
+        *self = Self::from_sorted(kept);
+        extracted.into_iter()
     }
 
-    #[test]
-    fn rotate_left() {
-        let mut tree = AVLTree::Empty;
-        tree.add(3usize);
-        tree.add(1usize);
-        tree.add(10usize);
-        tree.add(8usize);
-        tree.add(13usize);
-        tree.rotate_left();
-        assert_eq!(tree.value().unwrap(), &10);
-        assert_eq!(tree.left().value().unwrap(), &3);
-        assert_eq!(tree.left().right().value().unwrap(), &8);
+    /// Applies `f` to every value and collects the results into a new tree.
+    ///
+    /// `f` need not be monotone, so the results are inserted one by one and duplicates are merged.
+    pub fn map<U: Ord, F: Fn(&T) -> U>(&self, f: F) -> AVLTree<U> {
+        self.iter().map(f).collect()
     }
 
-    fn check_height<T: Ord>(tree: AVLTree<T>) -> bool {
-        let n = tree.len();
-        let h = tree.depth() as f64 - 1.;
-        let l = ((n + 1) as f64).log2() - 1.;
-        let r = 1.45 * ((n + 2) as f64).log2() - 1.32;
-        (l <= h) & (h < r)
+    /// Returns a new tree holding clones of the values matching `pred`, built in O(n).
+    pub fn filter<F: Fn(&T) -> bool>(&self, pred: F) -> Self
+    where
+        T: Clone,
+    {
+        Self::from_sorted(self.iter().filter(|v| pred(v)).cloned().collect())
     }
 
-    #[quickcheck]
-    fn len(v: HashSet<usize>) -> bool {
-        let mut tree = Empty;
-        for &vi in v.iter() {
-            tree.add(vi);
-        }
-        tree.len() == v.len()
+    /// Consumes the tree and splits it into `(matching, non-matching)` trees, each built in O(n).
+    pub fn partition<F: Fn(&T) -> bool>(self, pred: F) -> (Self, Self) {
+        let (matching, rest): (Vec<_>, Vec<_>) = self.into_iter().partition(|v| pred(v));
+        (Self::from_sorted(matching), Self::from_sorted(rest))
     }
 
-    #[quickcheck]
-    fn balance(v: HashSet<usize>) -> bool {
-        let mut tree = Empty;
-        for &vi in v.iter() {
-            tree.add(vi);
+    #[cfg(test)]
+    fn depth(&self) -> usize {
+        match *self {
+            Empty => 0,
+            NonEmpty(ref v) => core::cmp::max(v.left.depth(), v.right.depth()) + 1,
         }
-        check_height(tree)
     }
 
-    #[quickcheck]
-    fn into_iter(v: HashSet<usize>) -> bool {
-        let mut v: Vec<_> = v.into_iter().collect();
-        v.sort();
-        let mut tree = Empty;
+    pub fn iter(&self) -> RangeIter<'_, T> {
+        self.range_bounds::<T, _>(..)
+    }
+
+    /// Returns an iterator over the values in descending order.
+    ///
+    /// This walks the right spine first, the same as `iter().rev()`.
+    pub fn iter_rev(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.iter().rev()
+    }
+
+    /// Returns an iterator over the half-open range `[l, r)`.
+    ///
+    /// `l` is inclusive and `r` is exclusive; `None` leaves that side unbounded.
+    /// Use [`range_bounds`](AVLTree::range_bounds) for other combinations.
+    ///
+    /// If `l > r` the range is empty and the iterator yields nothing; unlike
+    /// `BTreeMap::range`, this does not panic.
+    pub fn range<K>(&self, l: Option<&K>, r: Option<&K>) -> RangeIter<'_, T>
+    where
+        T: Borrow<K>,
+        K: ?Sized + Ord,
+    {
+        let start = l.map_or(Bound::Unbounded, Bound::Included);
+        let end = r.map_or(Bound::Unbounded, Bound::Excluded);
+        RangeIter::new(&Boxed, self, start, end)
+    }
+
+    /// Like [`range`](AVLTree::range), but takes the bounds by value.
+    pub fn range_owned<K>(&self, l: Option<K>, r: Option<K>) -> RangeIter<'_, T>
+    where
+        T: Borrow<K>,
+        K: Ord,
+    {
+        self.range(l.as_ref(), r.as_ref())
+    }
+
+    /// Returns an iterator yielding mutable references to the values in `[l, r)`.
+    ///
+    /// As with [`iter_mut`](AVLTree::iter_mut), changing a value's ordering is a logic error.
+    pub fn range_mut<Q>(&mut self, l: Option<&Q>, r: Option<&Q>) -> RangeIterMut<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let remaining = self.count_range(l, r);
+        let mut iter = IterMut { stack: Vec::new() };
+        iter.traverse(self, &|v: &T| match l {
+            None => true,
+            Some(l) => l <= v.borrow(),
+        });
+        RangeIterMut { iter, remaining }
+    }
+
+    /// Returns an iterator over the values within `range`.
+    ///
+    /// A range whose start lies after its end is empty, as with [`range`](AVLTree::range).
+    pub fn range_bounds<K, R>(&self, range: R) -> RangeIter<'_, T>
+    where
+        T: Borrow<K>,
+        K: ?Sized + Ord,
+        R: RangeBounds<K>,
+    {
+        RangeIter::new(&Boxed, self, range.start_bound(), range.end_bound())
+    }
+
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.get(self, value)
+    }
+
+    /// Returns a mutable reference to the value equal to `value`.
+    ///
+    /// The caller must not change the value's ordering; only data that does not
+    /// take part in `Ord` may be modified.
+    pub fn get_mut<Q>(&mut self, value: &Q) -> Option<&mut T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match *self {
+            Empty => None,
+            NonEmpty(ref mut node) => match value.cmp(node.value.borrow()) {
+                Less => node.left.get_mut(value),
+                Equal => Some(&mut node.value),
+                Greater => node.right.get_mut(value),
+            },
+        }
+    }
+
+    /// Returns mutable references to the values equal to `a` and `b` at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a == b`.
+    pub fn get_disjoint_mut<Q>(&mut self, a: &Q, b: &Q) -> (Option<&mut T>, Option<&mut T>)
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        assert!(a != b, "get_disjoint_mut called with equal keys");
+        self.disjoint_mut(a, b)
+    }
+
+    fn disjoint_mut<Q>(&mut self, a: &Q, b: &Q) -> (Option<&mut T>, Option<&mut T>)
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // descends together until the paths to `a` and `b` part ways
+        let node = match *self {
+            Empty => return (None, None),
+            NonEmpty(ref mut node) => node,
+        };
+        let (oa, ob) = {
+            let v: &Q = node.value.borrow();
+            (a.cmp(v), b.cmp(v))
+        };
+        let Node {
+            ref mut value,
+            ref mut left,
+            ref mut right,
+            ..
+        } = **node;
+        match (oa, ob) {
+            (Less, Less) => left.disjoint_mut(a, b),
+            (Greater, Greater) => right.disjoint_mut(a, b),
+            _ => {
+                // `a != b`, so each of these is taken at most once
+                let (mut value, mut left, mut right) = (Some(value), Some(left), Some(right));
+                let mut pick = |ord, key: &Q| match ord {
+                    Less => left.take().and_then(|t| t.get_mut(key)),
+                    Equal => value.take(),
+                    Greater => right.take().and_then(|t| t.get_mut(key)),
+                };
+                let ra = pick(oa, a);
+                (ra, pick(ob, b))
+            }
+        }
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.get(value).is_some()
+    }
+
+    /// Removes and returns the value equal to `value`, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.remove_by(&|v: &T| value.cmp(v.borrow())).0
+    }
+
+    pub fn floor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.floor(self, key)
+    }
+
+    pub fn ceiling<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.ceiling(self, key)
+    }
+
+    /// Returns a cursor positioned just before the first value `>= key`.
+    pub fn lower_bound<Q>(&self, key: &Q) -> Cursor<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let mut path = Vec::new();
+        let mut found = 0;
+        let mut tree = self;
+        while let NonEmpty(ref node) = tree {
+            path.push(&**node);
+            if key <= node.value.borrow() {
+                found = path.len();
+                tree = &node.left;
+            } else {
+                tree = &node.right;
+            }
+        }
+        path.truncate(found);
+        Cursor { tree: self, path }
+    }
+
+    /// Returns the value closest to `key` under `dist`, preferring the lower value on ties.
+    pub fn nearest_by<F, D>(&self, key: &T, dist: F) -> Option<&T>
+    where
+        F: Fn(&T, &T) -> D,
+        D: Ord,
+    {
+        match (self.floor(key), self.ceiling(key)) {
+            (Some(lo), Some(hi)) if dist(key, hi) < dist(key, lo) => Some(hi),
+            (Some(lo), _) => Some(lo),
+            (None, hi) => hi,
+        }
+    }
+
+    pub fn predecessor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.predecessor(self, key)
+    }
+
+    pub fn successor<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.successor(self, key)
+    }
+
+    /// Returns the number of values strictly less than `key`.
+    pub fn rank<Q>(&self, key: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Boxed.rank(self, key)
+    }
+
+    /// Returns the 0-based sorted index of the value equal to `value`, or `None` if absent.
+    ///
+    /// Unlike [`rank`](AVLTree::rank), this requires the value to be present.
+    pub fn position<Q>(&self, value: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.get_full(value).map(|(i, _)| i)
+    }
+
+    /// Returns the rank of the value equal to `key` along with a reference to it.
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(usize, &T)>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match *self {
+            Empty => None,
+            NonEmpty(ref node) => match key.cmp(node.value.borrow()) {
+                Less => node.left.get_full(key),
+                Equal => Some((node.left.len(), &node.value)),
+                Greater => node
+                    .right
+                    .get_full(key)
+                    .map(|(i, v)| (node.left.len() + 1 + i, v)),
+            },
+        }
+    }
+
+    /// Returns the number of values in the half-open range `[l, r)` in O(log n).
+    pub fn count_range<Q>(&self, l: Option<&Q>, r: Option<&Q>) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let lo = l.map_or(0, |l| self.rank(l));
+        let hi = r.map_or(self.len(), |r| self.rank(r));
+        hi.saturating_sub(lo)
+    }
+
+    /// Returns whether any value lies in the half-open range `[l, r)`, in O(log n).
+    pub fn any_in_range<Q>(&self, l: Option<&Q>, r: Option<&Q>) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let mut tree = self;
+        while let NonEmpty(ref node) = tree {
+            let value = node.value.borrow();
+            if matches!(l, Some(l) if value < l) {
+                tree = &node.right;
+            } else if matches!(r, Some(r) if r <= value) {
+                tree = &node.left;
+            } else {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Folds `f` over the values in the half-open range `[l, r)` in ascending order.
+    pub fn fold_range<Q, B, F>(&self, l: Option<&Q>, r: Option<&Q>, init: B, f: F) -> B
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+        F: FnMut(B, &T) -> B,
+    {
+        self.range(l, r).fold(init, f)
+    }
+
+    /// Returns the `k`-th smallest value, counting from zero.
+    pub fn select(&self, k: usize) -> Option<&T> {
+        Boxed.select(self, k)
+    }
+
+    fn select_mut(&mut self, k: usize) -> Option<&mut T> {
+        match *self {
+            Empty => None,
+            NonEmpty(ref mut node) => {
+                let l = node.left.len();
+                match k.cmp(&l) {
+                    Less => node.left.select_mut(k),
+                    Equal => Some(&mut node.value),
+                    Greater => node.right.select_mut(k - l - 1),
+                }
+            }
+        }
+    }
+
+    pub fn entry(&mut self, value: T) -> Entry<'_, T> {
+        if self.contains(&value) {
+            let value = self.get_mut(&value).unwrap();
+            Entry::Occupied(OccupiedEntry { value })
+        } else {
+            Entry::Vacant(VacantEntry { tree: self, value })
+        }
+    }
+
+    pub fn get_or_insert(&mut self, value: T) -> &T {
+        self.entry(value).or_insert()
+    }
+
+    pub fn get_or_insert_with<Q, F>(&mut self, key: &Q, f: F) -> &T
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+        F: FnOnce(&Q) -> T,
+    {
+        if !self.contains(key) {
+            self.insert(f(key));
+        }
+        self.get(key).expect("new value is not equal to key")
+    }
+
+    /// Returns the smallest value.
+    #[deprecated(note = "shadowed by `Ord::min` on owned trees; use `first`")]
+    pub fn min(&self) -> Option<&T> {
+        self.first()
+    }
+
+    /// Returns the largest value.
+    #[deprecated(note = "shadowed by `Ord::max` on owned trees; use `last`")]
+    pub fn max(&self) -> Option<&T> {
+        self.last()
+    }
+
+    fn merge<'a, F>(&'a self, other: &'a Self, keep: F) -> Vec<&'a T>
+    where
+        F: Fn(bool, bool) -> bool,
+    {
+        // walks both trees in order; `keep(in_self, in_other)` decides whether to emit each value
+        let mut a = self.values().peekable();
+        let mut b = other.values().peekable();
+        let mut ret = Vec::new();
+        loop {
+            let (v, in_a, in_b) = match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(_), None) => (a.next().unwrap(), true, false),
+                (None, Some(_)) => (b.next().unwrap(), false, true),
+                (Some(x), Some(y)) => match x.cmp(y) {
+                    Less => (a.next().unwrap(), true, false),
+                    Greater => (b.next().unwrap(), false, true),
+                    Equal => {
+                        b.next();
+                        (a.next().unwrap(), true, true)
+                    }
+                },
+            };
+            if keep(in_a, in_b) {
+                ret.push(v);
+            }
+        }
+        ret
+    }
+
+    fn merged<F>(&self, other: &Self, keep: F) -> Self
+    where
+        T: Clone,
+        F: Fn(bool, bool) -> bool,
+    {
+        Self::from_sorted_iter(self.merge(other, keep).into_iter().cloned())
+    }
+
+    pub fn union(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.merged(other, |_, _| true)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.merged(other, |a, b| a && b)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.merged(other, |a, b| a && !b)
+    }
+
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.merged(other, |a, b| a != b)
+    }
+
+    /// Returns the size of the intersection in O(n + m) without building a result tree.
+    pub fn intersection_len(&self, other: &Self) -> usize {
+        self.merge_counts(other).1
+    }
+
+    /// Returns the size of the union in O(n + m) without building a result tree.
+    pub fn union_len(&self, other: &Self) -> usize {
+        let (a, both, b) = self.merge_counts(other);
+        a + both + b
+    }
+
+    /// Returns the size of the symmetric difference in O(n + m) without building a result tree.
+    pub fn symmetric_difference_len(&self, other: &Self) -> usize {
+        let (a, _, b) = self.merge_counts(other);
+        a + b
+    }
+
+    fn merge_counts(&self, other: &Self) -> (usize, usize, usize) {
+        // returns: (only in self, in both, only in other)
+        let mut a = self.values().peekable();
+        let mut b = other.values().peekable();
+        let mut counts = (0, 0, 0);
+        loop {
+            match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(_), None) => {
+                    counts.0 += a.count();
+                    break;
+                }
+                (None, Some(_)) => {
+                    counts.2 += b.count();
+                    break;
+                }
+                (Some(x), Some(y)) => match x.cmp(y) {
+                    Less => {
+                        a.next();
+                        counts.0 += 1;
+                    }
+                    Greater => {
+                        b.next();
+                        counts.2 += 1;
+                    }
+                    Equal => {
+                        a.next();
+                        b.next();
+                        counts.1 += 1;
+                    }
+                },
+            }
+        }
+        counts
+    }
+
+    /// Keeps only the values also present in `other`.
+    pub fn retain_intersection(&mut self, other: &Self) {
+        self.retain_membership(other, true);
+    }
+
+    /// Removes every value present in `other`.
+    pub fn remove_all(&mut self, other: &Self) {
+        self.retain_membership(other, false);
+    }
+
+    fn retain_membership(&mut self, other: &Self, present: bool) {
+        // `retain` visits values in sorted order, so `other` is walked alongside in one pass
+        let mut b = other.values().peekable();
+        self.retain(|x| {
+            while matches!(b.peek(), Some(y) if *y < x) {
+                b.next();
+            }
+            (b.peek() == Some(&x)) == present
+        });
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        if self.len() > other.len() {
+            return false;
+        }
+        let mut b = other.values();
+        'outer: for x in self.values() {
+            for y in &mut b {
+                match x.cmp(y) {
+                    Less => return false,
+                    Equal => continue 'outer,
+                    Greater => (),
+                }
+            }
+            return false;
+        }
+        true
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Verifies the ordering of the values, that every stored balance factor matches
+    /// the actual subtree heights and lies in `-1..=1`, and that subtree sizes are correct.
+    pub fn check_invariant(&self) -> bool {
+        self.values().zip(self.values().skip(1)).all(|(a, b)| a < b)
+            && Boxed.checked_height(self).is_some()
+    }
+
+    /// Returns references to all values in sorted order.
+    pub fn to_vec(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    /// Returns the `k` smallest values in ascending order.
+    pub fn k_smallest(&self, k: usize) -> Vec<&T> {
+        self.iter().take(k).collect()
+    }
+
+    /// Returns the `k` largest values in descending order.
+    pub fn k_largest(&self, k: usize) -> Vec<&T> {
+        self.iter().rev().take(k).collect()
+    }
+
+    /// Returns an iterator over `(rank, value)` pairs in sorted order, ranks counting from zero.
+    pub fn enumerate_iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.iter().enumerate()
+    }
+
+    /// Returns an iterator over each pair of adjacent values `(a, b)` with `a < b`.
+    pub fn pairs(&self) -> impl Iterator<Item = (&T, &T)> {
+        self.iter().zip(self.iter().skip(1))
+    }
+
+    /// Returns the smallest value.
+    pub fn first(&self) -> Option<&T> {
+        Boxed.first(self)
+    }
+
+    /// Returns the largest value.
+    pub fn last(&self) -> Option<&T> {
+        Boxed.last(self)
+    }
+
+    /// Returns the smallest value without removing it, in O(log n); see [`pop_min`](AVLTree::pop_min).
+    pub fn peek_min(&self) -> Option<&T> {
+        self.first()
+    }
+
+    /// Returns the largest value without removing it, in O(log n); see [`pop_max`](AVLTree::pop_max).
+    pub fn peek_max(&self) -> Option<&T> {
+        self.last()
+    }
+
+    /// Returns the minimum value under `f` in O(n), preferring the first in sorted order on ties.
+    pub fn min_by<F>(&self, f: F) -> Option<&T>
+    where
+        F: Fn(&T, &T) -> core::cmp::Ordering,
+    {
+        self.iter().min_by(|a, b| f(a, b))
+    }
+
+    /// Returns the maximum value under `f` in O(n), preferring the last in sorted order on ties.
+    pub fn max_by<F>(&self, f: F) -> Option<&T>
+    where
+        F: Fn(&T, &T) -> core::cmp::Ordering,
+    {
+        self.iter().max_by(|a, b| f(a, b))
+    }
+
+    #[cfg(test)]
+    fn value(&self) -> Option<&T> {
+        match *self {
+            Empty => None,
+            NonEmpty(ref v) => Some(&v.value),
+        }
+    }
+}
+
+impl<T> Drop for AVLTree<T> {
+    fn drop(&mut self) {
+        // detach subtrees onto a worklist so that dropping never recurses deeper than
+        // one level, however tall the tree is; only non-empty children are pushed, so
+        // dropping a leaf (including each detached one) allocates nothing
+        let mut stack = Vec::new();
+        if let NonEmpty(ref mut node) = *self {
+            push_children(&mut stack, node);
+        }
+        while let Some(mut tree) = stack.pop() {
+            if let NonEmpty(ref mut node) = tree {
+                push_children(&mut stack, node);
+            }
+        }
+    }
+}
+
+fn push_children<T>(stack: &mut Vec<AVLTree<T>>, node: &mut Node<T>) {
+    if let NonEmpty(_) = node.left {
+        stack.push(mem::take(&mut node.left));
+    }
+    if let NonEmpty(_) = node.right {
+        stack.push(mem::take(&mut node.right));
+    }
+}
+
+impl<T: Clone> Clone for AVLTree<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            Empty => Empty,
+            NonEmpty(ref node) => NonEmpty(node.clone()),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        match (self, source) {
+            (NonEmpty(node), NonEmpty(source)) => node.clone_from(source),
+            (tree, source) => *tree = source.clone(),
+        }
+    }
+}
+
+impl<T: Clone, L: Clone> Clone for Node<T, L> {
+    fn clone(&self) -> Self {
+        Node {
+            value: self.value.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+            balance_factor: self.balance_factor,
+            size: self.size,
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.value.clone_from(&source.value);
+        self.left.clone_from(&source.left);
+        self.right.clone_from(&source.right);
+        self.balance_factor = source.balance_factor;
+        self.size = source.size;
+    }
+}
+
+pub struct IntoIter<T> {
+    // values and whole subtrees not yet yielded, in sorted order
+    pending: VecDeque<Pending<T>>,
+    remaining: usize,
+}
+
+enum Pending<T> {
+    Value(T),
+    Tree(Box<Node<T>>),
+}
+
+impl<T> IntoIter<T> {
+    pub fn new(tree: AVLTree<T>) -> Self {
+        let remaining = tree.len();
+        let mut pending = VecDeque::new();
+        if let Some(node) = tree.into_node() {
+            pending.push_back(Pending::Tree(node));
+        }
+        IntoIter { pending, remaining }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.pending.pop_front()? {
+                Pending::Value(value) => {
+                    self.remaining -= 1;
+                    return Some(value);
+                }
+                Pending::Tree(node) => {
+                    let Node {
+                        value, left, right, ..
+                    } = *node;
+                    if let Some(right) = right.into_node() {
+                        self.pending.push_front(Pending::Tree(right));
+                    }
+                    self.pending.push_front(Pending::Value(value));
+                    if let Some(left) = left.into_node() {
+                        self.pending.push_front(Pending::Tree(left));
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.pending.pop_back()? {
+                Pending::Value(value) => {
+                    self.remaining -= 1;
+                    return Some(value);
+                }
+                Pending::Tree(node) => {
+                    let Node {
+                        value, left, right, ..
+                    } = *node;
+                    if let Some(left) = left.into_node() {
+                        self.pending.push_back(Pending::Tree(left));
+                    }
+                    self.pending.push_back(Pending::Value(value));
+                    if let Some(right) = right.into_node() {
+                        self.pending.push_back(Pending::Tree(right));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+struct Values<'a, T> {
+    stack: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Values<'a, T> {
+    fn new(tree: &'a AVLTree<T>) -> Self {
+        let mut values = Values { stack: Vec::new() };
+        values.traverse_left(tree);
+        values
+    }
+
+    fn traverse_left(&mut self, mut tree: &'a AVLTree<T>) {
+        while let NonEmpty(ref node) = tree {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Values<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.traverse_left(&node.right);
+        Some(&node.value)
+    }
+}
+
+pub struct IterMut<'a, T> {
+    stack: Vec<(&'a mut T, &'a mut AVLTree<T>)>,
+}
+
+impl<'a, T> IterMut<'a, T> {
+    fn new(tree: &'a mut AVLTree<T>) -> Self {
+        let mut iter = IterMut { stack: Vec::new() };
+        iter.traverse_left(tree);
+        iter
+    }
+
+    fn traverse_left(&mut self, mut tree: &'a mut AVLTree<T>) {
+        while let NonEmpty(ref mut node) = *tree {
+            let Node {
+                ref mut value,
+                ref mut left,
+                ref mut right,
+                ..
+            } = **node;
+            self.stack.push((value, right));
+            tree = left;
+        }
+    }
+
+    fn traverse<S: Fn(&T) -> bool>(&mut self, mut tree: &'a mut AVLTree<T>, after_start: &S) {
+        while let NonEmpty(ref mut node) = *tree {
+            let Node {
+                ref mut value,
+                ref mut left,
+                ref mut right,
+                ..
+            } = **node;
+            if after_start(value) {
+                self.stack.push((value, right));
+                tree = left;
+            } else {
+                tree = right;
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, right) = self.stack.pop()?;
+        self.traverse_left(right);
+        Some(value)
+    }
+}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+pub struct RangeIterMut<'a, T> {
+    iter: IterMut<'a, T>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for RangeIterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for RangeIterMut<'a, T> {}
+
+impl<'a, T> FusedIterator for RangeIterMut<'a, T> {}
+
+impl<T> IntoIterator for AVLTree<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLTree<T> {
+    type Item = &'a T;
+    type IntoIter = RangeIter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Mutating a value so that its ordering changes is a logic error; see [`AVLTree::iter_mut`].
+impl<'a, T> IntoIterator for &'a mut AVLTree<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T: PartialEq> PartialEq for AVLTree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.values().eq(other.values())
+    }
+}
+
+impl<T: Eq> Eq for AVLTree<T> {}
+
+impl<T: PartialOrd> PartialOrd for AVLTree<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        self.values().partial_cmp(other.values())
+    }
+}
+
+impl<T: Ord> Ord for AVLTree<T> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.values().cmp(other.values())
+    }
+}
+
+impl<T: Hash> Hash for AVLTree<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for v in self.values() {
+            v.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for AVLTree<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.values())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Ord> serde::Deserialize<'de> for AVLTree<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().collect())
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for AVLTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, v) in self.values().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// The error returned by [`AVLTree::from_iter_checked`], carrying the duplicated value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateError<T>(pub T);
+
+impl<T: fmt::Debug> fmt::Display for DuplicateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "duplicate value: {:?}", self.0)
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Empty;
+        for v in iter {
+            tree.insert(v);
+        }
+        tree
+    }
+}
+
+impl<T: Ord> From<Vec<T>> for AVLTree<T> {
+    fn from(mut v: Vec<T>) -> Self {
+        v.sort();
+        v.dedup();
+        Self::from_sorted(v)
+    }
+}
+
+impl<T: Ord, const N: usize> From<[T; N]> for AVLTree<T> {
+    fn from(arr: [T; N]) -> Self {
+        Self::from(Vec::from(arr))
+    }
+}
+
+impl<T> From<AVLTree<T>> for Vec<T> {
+    fn from(tree: AVLTree<T>) -> Self {
+        tree.into_iter().collect()
+    }
+}
+
+impl<T: Ord> Extend<T> for AVLTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for v in iter {
+            self.insert(v);
+        }
+    }
+}
+
+impl<'a, T: 'a + Ord + Copy> Extend<&'a T> for AVLTree<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T, Q> Index<&Q> for AVLTree<T>
+where
+    T: Ord + Borrow<Q>,
+    Q: ?Sized + Ord,
+{
+    type Output = T;
+    fn index(&self, index: &Q) -> &Self::Output {
+        self.get(index).unwrap()
+    }
+}
+
+impl<T: Ord> Index<usize> for AVLTree<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.select(index) {
+            Some(v) => v,
+            None => panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                self.len(),
+                index
+            ),
+        }
+    }
+}
+
+pub struct RangeIter<'a, T, S: Store<T> = Boxed> {
+    store: &'a S,
+    // the elements not yet yielded lie between the tops of the two stacks
+    stack: Vec<&'a Node<T, S::Link>>,
+    back_stack: Vec<&'a Node<T, S::Link>>,
+    remaining: usize,
+}
+
+impl<'a, T, S: Store<T>> RangeIter<'a, T, S> {
+    fn new<K>(store: &'a S, tree: &'a S::Link, start: Bound<&K>, end: Bound<&K>) -> Self
+    where
+        T: Borrow<K>,
+        K: ?Sized + Ord,
+    {
+        let after_start = |v: &T| match start {
+            Bound::Unbounded => true,
+            Bound::Included(k) => k <= v.borrow(),
+            Bound::Excluded(k) => k < v.borrow(),
+        };
+        let before_end = |v: &T| match end {
+            Bound::Unbounded => true,
+            Bound::Included(k) => v.borrow() <= k,
+            Bound::Excluded(k) => v.borrow() < k,
+        };
+        Self::new_by(store, tree, after_start, before_end)
+    }
+    fn new_by<A, E>(store: &'a S, tree: &'a S::Link, after_start: A, before_end: E) -> Self
+    where
+        A: Fn(&T) -> bool,
+        E: Fn(&T) -> bool,
+    {
+        // `after_start` and `before_end` must be monotone along the sorted order
+        let mut iter = RangeIter {
+            store,
+            stack: Vec::new(),
+            back_stack: Vec::new(),
+            remaining: 0,
+        };
+        iter.traverse(tree, &after_start);
+        iter.traverse_back(tree, &before_end);
+        match iter.stack.last() {
+            Some(first) if before_end(&first.value) => {
+                iter.remaining = store.count_prefix(tree, &before_end)
+                    - store.count_prefix(tree, &|v: &T| !after_start(v));
+            }
+            _ => iter.finish(),
+        }
+        iter
+    }
+    fn traverse_left(&mut self, mut tree: &'a S::Link) {
+        while let Some(node) = self.store.node(tree) {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+    fn traverse_right(&mut self, mut tree: &'a S::Link) {
+        while let Some(node) = self.store.node(tree) {
+            self.back_stack.push(node);
+            tree = &node.right;
+        }
+    }
+    fn traverse<A: Fn(&T) -> bool>(&mut self, mut tree: &'a S::Link, after_start: &A) {
+        while let Some(node) = self.store.node(tree) {
+            if after_start(&node.value) {
+                self.stack.push(node);
+                tree = &node.left;
+            } else {
+                tree = &node.right;
+            }
+        }
+    }
+    fn traverse_back<E: Fn(&T) -> bool>(&mut self, mut tree: &'a S::Link, before_end: &E) {
+        while let Some(node) = self.store.node(tree) {
+            if before_end(&node.value) {
+                self.back_stack.push(node);
+                tree = &node.right;
+            } else {
+                tree = &node.left;
+            }
+        }
+    }
+    fn finish(&mut self) {
+        self.stack.clear();
+        self.back_stack.clear();
+        self.remaining = 0;
+    }
+}
+
+impl<'a, T, S: Store<T>> Iterator for RangeIter<'a, T, S> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.remaining -= 1;
+        match self.back_stack.last() {
+            Some(last) if core::ptr::eq(*last, node) => self.finish(),
+            _ => self.traverse_left(&node.right),
+        }
+        Some(&node.value)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, S: Store<T>> DoubleEndedIterator for RangeIter<'a, T, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back_stack.pop()?;
+        self.remaining -= 1;
+        match self.stack.last() {
+            Some(first) if core::ptr::eq(*first, node) => self.finish(),
+            _ => self.traverse_right(&node.left),
+        }
+        Some(&node.value)
+    }
+}
+
+impl<'a, T, S: Store<T>> ExactSizeIterator for RangeIter<'a, T, S> {}
+
+impl<'a, T, S: Store<T>> FusedIterator for RangeIter<'a, T, S> {}
+
+/// Cloning a `RangeIter` yields an independent cursor at the same position.
+impl<'a, T, S: Store<T>> Clone for RangeIter<'a, T, S> {
+    fn clone(&self) -> Self {
+        RangeIter {
+            store: self.store,
+            stack: self.stack.clone(),
+            back_stack: self.back_stack.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A position between two adjacent values of a tree, created by [`AVLTree::lower_bound`].
+pub struct Cursor<'a, T> {
+    tree: &'a AVLTree<T>,
+    // root-to-node path of the next value; empty when the cursor is past the last value
+    path: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    /// Returns the value after the cursor, if any.
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.path.last().map(|node| &node.value)
+    }
+
+    /// Returns the value before the cursor, if any.
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        let node = match self.path.last() {
+            Some(node) => *node,
+            None => return self.tree.as_node().map(Self::rightmost),
+        };
+        if let NonEmpty(ref left) = node.left {
+            return Some(Self::rightmost(left));
+        }
+        // otherwise it is the nearest ancestor whose right subtree holds the cursor
+        self.path
+            .windows(2)
+            .rev()
+            .find(|w| !Self::is_left(w[0], w[1]))
+            .map(|w| &w[0].value)
+    }
+
+    /// Moves the cursor past the next value. Does nothing at the end.
+    pub fn move_next(&mut self) {
+        let node = match self.path.last() {
+            Some(node) => *node,
+            None => return,
+        };
+        if let NonEmpty(ref right) = node.right {
+            Self::descend(&mut self.path, right, |n| &n.left);
+            return;
+        }
+        // climb until we leave a left subtree
+        let mut child = self.path.pop();
+        while let Some(parent) = self.path.last() {
+            if Self::is_left(parent, child.unwrap()) {
+                return;
+            }
+            child = self.path.pop();
+        }
+    }
+
+    /// Moves the cursor back over the previous value. Does nothing at the start.
+    pub fn move_prev(&mut self) {
+        if self.peek_prev().is_none() {
+            return;
+        }
+        let node = match self.path.last() {
+            Some(node) => *node,
+            None => match *self.tree {
+                Empty => unreachable!(),
+                NonEmpty(ref root) => return Self::descend(&mut self.path, root, |n| &n.right),
+            },
+        };
+        if let NonEmpty(ref left) = node.left {
+            return Self::descend(&mut self.path, left, |n| &n.right);
+        }
+        // climb until we leave a right subtree
+        let mut child = self.path.pop();
+        while let Some(parent) = self.path.last() {
+            if !Self::is_left(parent, child.unwrap()) {
+                return;
+            }
+            child = self.path.pop();
+        }
+    }
+
+    fn rightmost(mut node: &'a Node<T>) -> &'a T {
+        while let NonEmpty(ref right) = node.right {
+            node = right;
+        }
+        &node.value
+    }
+
+    fn descend<F>(path: &mut Vec<&'a Node<T>>, mut node: &'a Node<T>, next: F)
+    where
+        F: Fn(&'a Node<T>) -> &'a AVLTree<T>,
+    {
+        path.push(node);
+        while let NonEmpty(ref child) = *next(node) {
+            node = child;
+            path.push(node);
+        }
+    }
+
+    fn is_left(parent: &Node<T>, child: &Node<T>) -> bool {
+        match parent.left {
+            NonEmpty(ref left) => core::ptr::eq(&**left, child),
+            Empty => false,
+        }
+    }
+}
+
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+
+pub struct OccupiedEntry<'a, T> {
+    value: &'a mut T,
+}
+
+pub struct VacantEntry<'a, T> {
+    tree: &'a mut AVLTree<T>,
+    value: T,
+}
+
+impl<'a, T: Ord> Entry<'a, T> {
+    /// Inserts the value if it is vacant, and returns a reference to the stored value.
+    pub fn or_insert(self) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(),
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        match *self {
+            Entry::Occupied(ref entry) => entry.get(),
+            Entry::Vacant(ref entry) => entry.get(),
+        }
+    }
+}
+
+impl<'a, T> OccupiedEntry<'a, T> {
+    pub fn get(&self) -> &T {
+        self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value
+    }
+
+    pub fn into_mut(self) -> &'a mut T {
+        self.value
+    }
+}
+
+impl<'a, T> VacantEntry<'a, T> {
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+impl<'a, T: Ord> VacantEntry<'a, T> {
+    pub fn insert(self) -> &'a mut T {
+        let k = self.tree.rank(&self.value);
+        self.tree.insert(self.value);
+        self.tree.select_mut(k).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn rotate_right() {
+        let mut tree = AVLTree::Empty;
+        tree.add(3usize);
+        tree.add(4usize);
+        tree.add(1usize);
+        tree.add(2usize);
+        tree.add(0usize);
+        assert_eq!(tree.left().value().unwrap(), &1);
+        assert_eq!(tree.left().left().value().unwrap(), &0);
+        Boxed.rotate_right(&mut tree);
+        assert_eq!(tree.value().unwrap(), &1);
+        assert_eq!(tree.left().value().unwrap(), &0);
+        assert_eq!(tree.right().value().unwrap(), &3);
+        assert_eq!(tree.right().left().value().unwrap(), &2);
+    }
+
+    #[test]
+    fn rotate_left() {
+        let mut tree = AVLTree::Empty;
+        tree.add(3usize);
+        tree.add(1usize);
+        tree.add(10usize);
+        tree.add(8usize);
+        tree.add(13usize);
+        Boxed.rotate_left(&mut tree);
+        assert_eq!(tree.value().unwrap(), &10);
+        assert_eq!(tree.left().value().unwrap(), &3);
+        assert_eq!(tree.left().right().value().unwrap(), &8);
+    }
+
+    pub(crate) fn check_height<T: Ord>(tree: AVLTree<T>) -> bool {
+        let n = tree.len();
+        let h = tree.depth() as f64 - 1.;
+        let l = ((n + 1) as f64).log2() - 1.;
+        let r = 1.45 * ((n + 2) as f64).log2() - 1.32;
+        (l <= h) & (h < r)
+    }
+
+    pub(crate) fn check_factors<T: Ord>(tree: &AVLTree<T>) -> bool {
+        match *tree {
+            Empty => true,
+            NonEmpty(ref v) => {
+                let bf = v.right.depth() as i64 - v.left.depth() as i64;
+                bf == v.balance_factor as i64 && check_factors(&v.left) && check_factors(&v.right)
+            }
+        }
+    }
+
+    pub(crate) fn check_sizes<T>(tree: &AVLTree<T>) -> bool {
+        match *tree {
+            Empty => true,
+            NonEmpty(ref v) => {
+                v.size == 1 + v.left.len() + v.right.len()
+                    && check_sizes(&v.left)
+                    && check_sizes(&v.right)
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn len(v: HashSet<usize>) -> bool {
+        let mut tree = Empty;
+        for &vi in v.iter() {
+            tree.add(vi);
+        }
+        tree.len() == v.len()
+    }
+
+    #[quickcheck]
+    fn balance(v: HashSet<usize>) -> bool {
+        let mut tree = Empty;
+        for &vi in v.iter() {
+            tree.add(vi);
+        }
+        check_height(tree)
+    }
+
+    #[quickcheck]
+    fn into_iter(v: HashSet<usize>) -> bool {
+        let mut v: Vec<_> = v.into_iter().collect();
+        v.sort();
+        let mut tree = Empty;
+        for &vi in v.iter() {
+            tree.add(vi);
+        }
+        let w: Vec<_> = tree.into_iter().collect();
+        (0..v.len()).all(|i| v[i] == w[i])
+    }
+
+    #[quickcheck]
+    fn iter(v: HashSet<usize>) -> bool {
+        let mut v: Vec<_> = v.into_iter().collect();
+        v.sort();
+        let mut tree = Empty;
         for &vi in v.iter() {
             tree.add(vi);
         }
-        let w: Vec<_> = tree.into_iter().collect();
-        (0..v.len()).all(|i| v[i] == w[i])
+        let w: Vec<_> = tree.iter().collect();
+        (0..v.len()).all(|i| v[i] == *w[i])
+    }
+
+    #[quickcheck]
+    fn from_iter(v: HashSet<usize>) {
+        let n = v.len();
+        let w = v.clone();
+        let mut w: Vec<_> = w.into_iter().collect();
+        w.sort();
+        let tree: AVLTree<_> = v.into_iter().collect();
+        assert_eq!(tree.len(), n);
+        for (i, a) in tree.into_iter().enumerate() {
+            assert_eq!(a, w[i]);
+        }
+    }
+
+    #[quickcheck]
+    fn duplicates(v: Vec<i64>) -> bool {
+        let tree = v.into_iter().collect::<AVLTree<_>>();
+        let mut v: Vec<_> = tree.into_iter().collect();
+        let n = v.len();
+        v.dedup();
+        n == v.len()
+    }
+
+    #[quickcheck]
+    fn get_and_index(v: HashSet<usize>, indices: Vec<usize>) -> bool {
+        let w = v.clone();
+        let tree: AVLTree<_> = v.into_iter().collect();
+        w.iter().all(|wi| tree.get(wi).is_some())
+            && indices.iter().all(|i| w.get(i) == tree.get(i))
+            && w.iter().all(|wi| tree[wi] == *wi)
+    }
+
+    #[quickcheck]
+    fn rangeiter(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let (l, r) = if l < r { (l, r) } else { (r, l) };
+        let mut v: Vec<_> = v.into_iter().collect();
+        v.sort();
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let li = v.binary_search(&l).unwrap_or_else(|x| x);
+        let ri = v.binary_search(&r).unwrap_or_else(|x| x);
+        let w: Vec<_> = tree.range(Some(&l), Some(&r)).collect();
+        (0..(ri - li)).all(|i| v[i + li] == *w[i])
+    }
+
+    #[quickcheck]
+    fn pop_min(v: HashSet<usize>) -> bool {
+        let mut w: Vec<_> = v.iter().copied().collect();
+        w.sort();
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        let mut popped = Vec::new();
+        while let Some(x) = tree.pop_min() {
+            if !check_factors(&tree) {
+                return false;
+            }
+            popped.push(x);
+        }
+        tree.is_empty() && popped == w
+    }
+
+    #[quickcheck]
+    fn pop_max(v: HashSet<usize>) -> bool {
+        let mut w: Vec<_> = v.iter().copied().collect();
+        w.sort();
+        w.reverse();
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        let mut popped = Vec::new();
+        while let Some(x) = tree.pop_max() {
+            if !check_factors(&tree) {
+                return false;
+            }
+            popped.push(x);
+        }
+        tree.is_empty() && popped == w
+    }
+
+    #[quickcheck]
+    fn min_max(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        tree.first() == v.iter().min() && tree.last() == v.iter().max()
+    }
+
+    #[quickcheck]
+    fn clone(v: HashSet<usize>, w: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let before: Vec<_> = tree.iter().copied().collect();
+        let cloned = tree.clone();
+        for wi in w {
+            tree.insert(wi);
+        }
+        tree.pop_min();
+        let after: Vec<_> = cloned.iter().copied().collect();
+        before == after && check_factors(&cloned) && check_height(cloned)
+    }
+
+    #[quickcheck]
+    fn clone_from(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let source: AVLTree<_> = v.into_iter().collect();
+        let mut tree: AVLTree<_> = w.into_iter().collect();
+        tree.clone_from(&source);
+        let a: Vec<_> = source.iter().collect();
+        let b: Vec<_> = tree.iter().collect();
+        a == b && check_factors(&tree)
+    }
+
+    #[quickcheck]
+    fn contains(v: HashSet<usize>, indices: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        v.iter().all(|vi| tree.contains(vi))
+            && indices.iter().all(|i| v.contains(i) == tree.contains(i))
+    }
+
+    #[quickcheck]
+    fn eq(v: Vec<usize>, w: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let reversed: AVLTree<_> = v.iter().rev().copied().collect();
+        let other: AVLTree<_> = w.iter().copied().collect();
+        let v: HashSet<_> = v.into_iter().collect();
+        tree == reversed && (tree == other) == (v == w)
+    }
+
+    #[test]
+    fn eq_insertion_order() {
+        let a: AVLTree<_> = vec![1, 2, 3, 4, 5].into_iter().collect();
+        let b: AVLTree<_> = vec![4, 2, 5, 1, 3].into_iter().collect();
+        let c: AVLTree<_> = vec![1, 2, 3, 4].into_iter().collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[quickcheck]
+    fn iter_mut(v: HashSet<u32>) -> bool {
+        let mut w: Vec<_> = v.iter().map(|&x| x as u64 * 2).collect();
+        w.sort();
+        let mut tree: AVLTree<_> = v.into_iter().map(u64::from).collect();
+        for x in tree.iter_mut() {
+            *x *= 2;
+        }
+        let got: Vec<_> = tree.iter().copied().collect();
+        got == w && check_factors(&tree) && w.iter().all(|x| tree.contains(x))
+    }
+
+    #[quickcheck]
+    fn rangeiter_rev(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let mut forward: Vec<_> = tree.range(Some(&l), Some(&r)).collect();
+        forward.reverse();
+        let backward: Vec<_> = tree.range(Some(&l), Some(&r)).rev().collect();
+        let all: Vec<_> = tree.iter().rev().collect();
+        let mut sorted: Vec<_> = tree.iter().collect();
+        sorted.reverse();
+        forward == backward && all == sorted
+    }
+
+    #[quickcheck]
+    fn rangeiter_alternate(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let expected: Vec<_> = tree.range(Some(&l), Some(&r)).collect();
+        let mut iter = tree.range(Some(&l), Some(&r));
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(x) = iter.next() {
+            front.push(x);
+            match iter.next_back() {
+                Some(x) => back.push(x),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        front == expected && iter.next().is_none() && iter.next_back().is_none()
+    }
+
+    #[quickcheck]
+    fn into_iter_size_hint(v: HashSet<usize>, k: usize) -> bool {
+        let n = v.len();
+        let k = if n == 0 { 0 } else { k % (n + 1) };
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let mut iter = tree.into_iter();
+        for _ in 0..k {
+            iter.next();
+        }
+        iter.size_hint() == (n - k, Some(n - k)) && iter.len() == n - k
+    }
+
+    #[test]
+    fn fused() {
+        fn assert_fused<I: FusedIterator>(_: &I) {}
+        let mut tree: AVLTree<_> = (0..10).collect();
+        assert_fused(&tree.range(Some(&2), Some(&5)));
+        assert_fused(&tree.iter_mut());
+        assert_fused(&tree.into_iter());
+    }
+
+    #[quickcheck]
+    fn range_bounds(v: HashSet<usize>, a: usize, b: usize) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        let check = |got: Vec<&usize>, f: &dyn Fn(usize) -> bool| {
+            got.into_iter()
+                .copied()
+                .eq(w.iter().copied().filter(|&x| f(x)))
+        };
+        check(tree.range_bounds(a..b).collect(), &|x| a <= x && x < b)
+            && check(tree.range_bounds(a..=b).collect(), &|x| a <= x && x <= b)
+            && check(tree.range_bounds(..b).collect(), &|x| x < b)
+            && check(tree.range_bounds(a..).collect(), &|x| a <= x)
+            && check(tree.range_bounds(..).collect(), &|_| true)
+            && check(
+                tree.range_bounds(a..=b)
+                    .rev()
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .collect(),
+                &|x| a <= x && x <= b,
+            )
+    }
+
+    #[test]
+    fn range_bounds_edges() {
+        let tree: AVLTree<_> = (0..10).collect();
+        let collect = |it: RangeIter<'_, i32>| it.copied().collect::<Vec<_>>();
+        assert_eq!(collect(tree.range_bounds(3..6)), vec![3, 4, 5]);
+        assert_eq!(collect(tree.range_bounds(3..=6)), vec![3, 4, 5, 6]);
+        assert_eq!(collect(tree.range_bounds(..2)), vec![0, 1]);
+        assert_eq!(collect(tree.range_bounds(8..)), vec![8, 9]);
+        let (hi, lo) = (6, 3);
+        assert_eq!(collect(tree.range_bounds(hi..lo)), Vec::<i32>::new());
+        assert_eq!(collect(tree.range_bounds(6..6)), Vec::<i32>::new());
+        assert_eq!(collect(tree.range_bounds(6..=6)), vec![6]);
+        assert_eq!(
+            collect(tree.range_bounds((Bound::Excluded(3), Bound::Excluded(6)))),
+            vec![4, 5]
+        );
+    }
+
+    #[quickcheck]
+    fn range_half_open(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let (l, r) = if l < r { (l, r) } else { (r, l) };
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        tree.insert(l);
+        tree.insert(r);
+        let w: Vec<_> = tree.range(Some(&l), Some(&r)).copied().collect();
+        let expected: Vec<_> = tree.iter().copied().filter(|&x| l <= x && x < r).collect();
+        w == expected && (l == r || w.first() == Some(&l)) && !w.contains(&r)
+    }
+
+    #[test]
+    fn range_excludes_end() {
+        let tree: AVLTree<_> = vec![1, 3, 5, 7, 9].into_iter().collect();
+        let w: Vec<_> = tree.range(Some(&3), Some(&7)).copied().collect();
+        assert_eq!(w, vec![3, 5]);
+        let w: Vec<_> = tree.range(Some(&3), Some(&7)).rev().copied().collect();
+        assert_eq!(w, vec![5, 3]);
+        assert_eq!(tree.range(Some(&5), Some(&5)).next(), None);
+        let w: Vec<_> = tree.range(None, Some(&9)).copied().collect();
+        assert_eq!(w, vec![1, 3, 5, 7]);
+    }
+
+    #[derive(Debug, Clone)]
+    struct Keyed {
+        key: usize,
+        count: usize,
+    }
+
+    impl PartialEq for Keyed {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+
+    impl Eq for Keyed {}
+
+    impl PartialOrd for Keyed {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Keyed {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    impl Borrow<usize> for Keyed {
+        fn borrow(&self) -> &usize {
+            &self.key
+        }
+    }
+
+    #[quickcheck]
+    fn get_mut(v: HashSet<usize>, indices: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().map(|&key| Keyed { key, count: 0 }).collect();
+        let before = tree.clone();
+        for i in indices.iter() {
+            if let Some(e) = tree.get_mut(i) {
+                e.count += 1;
+            }
+        }
+        tree == before
+            && check_factors(&tree)
+            && indices
+                .iter()
+                .all(|i| tree.get_mut(i).is_some() == v.contains(i))
+            && v.iter()
+                .all(|k| tree.get(k).unwrap().count == indices.iter().filter(|&i| i == k).count())
+    }
+
+    #[quickcheck]
+    fn floor(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        keys.iter().chain(w.iter()).all(|k| {
+            let expected = match w.binary_search(k) {
+                Ok(i) => Some(&w[i]),
+                Err(0) => None,
+                Err(i) => Some(&w[i - 1]),
+            };
+            tree.floor(k) == expected
+        })
+    }
+
+    #[quickcheck]
+    fn ceiling(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        keys.iter().chain(w.iter()).all(|k| {
+            let expected = match w.binary_search(k) {
+                Ok(i) => Some(&w[i]),
+                Err(i) => w.get(i),
+            };
+            tree.ceiling(k) == expected
+        })
+    }
+
+    #[quickcheck]
+    fn predecessor_successor(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        keys.iter().chain(w.iter()).all(|k| {
+            let i = w.binary_search(k).unwrap_or_else(|x| x);
+            let j = w.binary_search(k).map_or_else(|x| x, |x| x + 1);
+            let pred = if i == 0 { None } else { Some(&w[i - 1]) };
+            tree.predecessor(k) == pred && tree.successor(k) == w.get(j)
+        })
+    }
+
+    #[test]
+    fn predecessor_successor_edges() {
+        let tree: AVLTree<_> = vec![10, 20, 30].into_iter().collect();
+        assert_eq!(tree.predecessor(&20), Some(&10));
+        assert_eq!(tree.successor(&20), Some(&30));
+        assert_eq!(tree.predecessor(&25), Some(&20));
+        assert_eq!(tree.successor(&25), Some(&30));
+        assert_eq!(tree.predecessor(&10), None);
+        assert_eq!(tree.successor(&30), None);
+        assert_eq!(tree.predecessor(&5), None);
+        assert_eq!(tree.successor(&35), None);
+        assert_eq!(tree.predecessor(&35), Some(&30));
+        assert_eq!(tree.successor(&5), Some(&10));
+    }
+
+    #[quickcheck]
+    fn sizes(v: Vec<usize>, pops: usize) -> bool {
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        if !check_sizes(&tree) {
+            return false;
+        }
+        for i in 0..pops % 64 {
+            if i % 2 == 0 {
+                tree.pop_min();
+            } else {
+                tree.pop_max();
+            }
+            if !check_sizes(&tree) {
+                return false;
+            }
+        }
+        check_sizes(&tree.clone())
+    }
+
+    #[quickcheck]
+    fn rank(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        keys.iter()
+            .chain(w.iter())
+            .all(|k| tree.rank(k) == w.iter().filter(|&x| x < k).count())
+    }
+
+    #[quickcheck]
+    fn select(v: HashSet<usize>, k: usize) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        (0..w.len()).all(|i| tree.select(i) == Some(&w[i]))
+            && tree.select(w.len()).is_none()
+            && tree.select(w.len() + k % 8).is_none()
+    }
+
+    #[quickcheck]
+    fn index_usize(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        (0..w.len()).all(|i| tree[i] == w[i])
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 3")]
+    fn index_usize_out_of_bounds() {
+        let tree: AVLTree<usize> = vec![1, 2, 3].into_iter().collect();
+        let _ = tree[3];
+    }
+
+    #[quickcheck]
+    fn union(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let u = a.union(&b);
+        let mut expected: Vec<_> = v.union(&w).copied().collect();
+        expected.sort();
+        let got: Vec<_> = u.iter().copied().collect();
+        got == expected && check_factors(&u) && check_sizes(&u) && check_height(u)
+    }
+
+    #[quickcheck]
+    fn intersection(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let i = a.intersection(&b);
+        let mut expected: Vec<_> = v.intersection(&w).copied().collect();
+        expected.sort();
+        let got: Vec<_> = i.iter().copied().collect();
+        got == expected && check_factors(&i) && check_sizes(&i) && a.intersection(&a) == a
+    }
+
+    #[test]
+    fn intersection_disjoint() {
+        let a: AVLTree<_> = (0..10).collect();
+        let b: AVLTree<_> = (10..20).collect();
+        assert!(a.intersection(&b).is_empty());
+        assert!(a.intersection(&Empty).is_empty());
+    }
+
+    #[quickcheck]
+    fn difference(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let d = a.difference(&b);
+        let mut expected: Vec<_> = v.difference(&w).copied().collect();
+        expected.sort();
+        let got: Vec<_> = d.iter().copied().collect();
+        got == expected && check_factors(&d) && check_sizes(&d)
+    }
+
+    #[quickcheck]
+    fn symmetric_difference(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let s = a.symmetric_difference(&b);
+        let mut expected: Vec<_> = v.symmetric_difference(&w).copied().collect();
+        expected.sort();
+        let got: Vec<_> = s.iter().copied().collect();
+        got == expected
+            && s == a.difference(&b).union(&b.difference(&a))
+            && check_factors(&s)
+            && check_sizes(&s)
+    }
+
+    #[quickcheck]
+    fn is_subset(v: HashSet<usize>, w: HashSet<usize>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let u = a.union(&b);
+        a.is_subset(&b) == v.is_subset(&w)
+            && a.is_superset(&b) == v.is_superset(&w)
+            && a.is_subset(&a)
+            && a.is_subset(&u)
+            && u.is_superset(&b)
+            && Empty.is_subset(&a)
+    }
+
+    #[quickcheck]
+    fn split_off(v: HashSet<usize>, key: usize) -> bool {
+        let mut w: Vec<_> = v.iter().copied().collect();
+        w.sort();
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        let right = tree.split_off(&key);
+        let a: Vec<_> = tree.iter().copied().collect();
+        let b: Vec<_> = right.iter().copied().collect();
+        let i = w.binary_search(&key).unwrap_or_else(|x| x);
+        a == w[..i]
+            && b == w[i..]
+            && check_factors(&tree)
+            && check_factors(&right)
+            && check_sizes(&tree)
+            && check_sizes(&right)
+            && check_height(tree)
+            && check_height(right)
+    }
+
+    #[test]
+    fn split_off_skewed() {
+        for n in 0..100 {
+            for key in 0..=n {
+                let mut tree: AVLTree<_> = (0..n).collect();
+                let right = tree.split_off(&key);
+                assert!(tree.iter().copied().eq(0..key));
+                assert!(right.iter().copied().eq(key..n));
+                assert!(check_factors(&tree) && check_factors(&right));
+                assert!(check_sizes(&tree) && check_sizes(&right));
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn append(v: HashSet<usize>, w: HashSet<usize>, offset: bool) -> bool {
+        let shift = if offset { usize::MAX / 2 } else { 0 };
+        let w: HashSet<_> = w.into_iter().map(|x| (x / 2).wrapping_add(shift)).collect();
+        let mut a: AVLTree<_> = v.iter().copied().collect();
+        let mut b: AVLTree<_> = w.iter().copied().collect();
+        a.append(&mut b);
+        let mut expected: Vec<_> = v.union(&w).copied().collect();
+        expected.sort();
+        b.is_empty()
+            && a.iter().copied().eq(expected.into_iter())
+            && check_factors(&a)
+            && check_sizes(&a)
+            && check_height(a)
+    }
+
+    #[test]
+    fn append_disjoint() {
+        for n in 0..50 {
+            for m in 0..50 {
+                let mut a: AVLTree<_> = (0..n).collect();
+                let mut b: AVLTree<_> = (n..n + m).collect();
+                a.append(&mut b);
+                assert!(b.is_empty());
+                assert!(a.iter().copied().eq(0..n + m));
+                assert!(check_factors(&a) && check_sizes(&a));
+                let mut c: AVLTree<_> = (0..n).collect();
+                let mut d: AVLTree<_> = (n..n + m).collect();
+                d.append(&mut c);
+                assert!(c.is_empty());
+                assert!(d.iter().copied().eq(0..n + m));
+                assert!(check_factors(&d) && check_sizes(&d));
+            }
+        }
+    }
+
+    #[test]
+    fn entry() {
+        let mut tree: AVLTree<_> = vec![1, 3, 5]
+            .into_iter()
+            .map(|key| Keyed { key, count: 0 })
+            .collect();
+        match tree.entry(Keyed { key: 3, count: 7 }) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.get().count, 0);
+                e.get_mut().count += 1;
+            }
+            Entry::Vacant(_) => panic!("expected occupied"),
+        }
+        assert_eq!(tree.get(&3).unwrap().count, 1);
+        match tree.entry(Keyed { key: 4, count: 7 }) {
+            Entry::Occupied(_) => panic!("expected vacant"),
+            Entry::Vacant(e) => {
+                assert_eq!(e.get().key, 4);
+                e.insert().count += 1;
+            }
+        }
+        assert_eq!(tree.get(&4).unwrap().count, 8);
+        tree.entry(Keyed { key: 0, count: 0 }).or_insert().count += 2;
+        tree.entry(Keyed { key: 0, count: 9 }).or_insert().count += 2;
+        assert_eq!(tree.get(&0).unwrap().count, 4);
+        assert_eq!(tree.len(), 5);
+        assert!(check_factors(&tree) && check_sizes(&tree));
+    }
+
+    #[quickcheck]
+    fn entry_vacant(v: HashSet<usize>, w: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        w.into_iter().all(|x| *tree.entry(x).or_insert() == x) && check_factors(&tree)
+    }
+
+    #[test]
+    fn replace() {
+        let mut tree: AVLTree<_> = (0..5).map(|key| Keyed { key, count: 0 }).collect();
+        let old = tree.replace(Keyed { key: 2, count: 9 }).unwrap();
+        assert_eq!((old.key, old.count), (2, 0));
+        assert_eq!(tree.get(&2).unwrap().count, 9);
+        assert!(tree.replace(Keyed { key: 7, count: 1 }).is_none());
+        assert_eq!(tree.get(&7).unwrap().count, 1);
+        assert_eq!(tree.len(), 6);
+        assert!(check_factors(&tree) && check_sizes(&tree));
+    }
+
+    #[test]
+    fn get_or_insert() {
+        let mut tree: AVLTree<_> = (0..5).map(|key| Keyed { key, count: 0 }).collect();
+        assert_eq!(tree.get_or_insert(Keyed { key: 2, count: 9 }).count, 0);
+        assert_eq!(tree.get_or_insert(Keyed { key: 8, count: 9 }).count, 9);
+        assert_eq!(
+            tree.get_or_insert_with(&3, |&key| Keyed { key, count: 5 })
+                .count,
+            0
+        );
+        assert_eq!(
+            tree.get_or_insert_with(&6, |&key| Keyed { key, count: 5 })
+                .count,
+            5
+        );
+        assert_eq!(tree.len(), 7);
+        assert!(check_factors(&tree) && check_sizes(&tree));
+    }
+
+    #[quickcheck]
+    fn get_or_insert_with(v: HashSet<usize>, w: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let all_found = w.iter().all(|x| *tree.get_or_insert_with(x, |&k| k) == *x);
+        let expected: HashSet<_> = v.into_iter().chain(w).collect();
+        all_found && tree.len() == expected.len() && check_factors(&tree)
+    }
+
+    #[cfg(feature = "serde")]
+    #[quickcheck]
+    fn serde_roundtrip(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let json = serde_json::to_string(&tree).unwrap();
+        let expected = serde_json::to_string(&tree.iter().collect::<Vec<_>>()).unwrap();
+        let back: AVLTree<usize> = serde_json::from_str(&json).unwrap();
+        json == expected && back == tree && check_factors(&back)
+    }
+
+    #[quickcheck]
+    fn extend(v: HashSet<usize>, w: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let mut by_ref = tree.clone();
+        tree.extend(w.iter().copied());
+        by_ref.extend(&w);
+        let mut expected: Vec<_> = v.into_iter().chain(w).collect();
+        expected.sort();
+        expected.dedup();
+        tree.iter().copied().eq(expected.into_iter())
+            && tree == by_ref
+            && check_factors(&tree)
+            && check_sizes(&tree)
+            && check_height(tree)
+    }
+
+    #[quickcheck]
+    fn retain(v: HashSet<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        tree.retain(|x| x % 2 == 0);
+        let mut expected: Vec<_> = v.into_iter().filter(|x| x % 2 == 0).collect();
+        expected.sort();
+        tree.iter().copied().eq(expected.into_iter())
+            && check_factors(&tree)
+            && check_sizes(&tree)
+            && check_height(tree)
+    }
+
+    #[quickcheck]
+    fn drain(v: HashSet<usize>) -> bool {
+        let mut expected: Vec<_> = v.iter().copied().collect();
+        expected.sort();
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        let drained: Vec<_> = tree.drain().collect();
+        tree.insert(1);
+        drained == expected && tree.len() == 1
+    }
+
+    #[quickcheck]
+    fn extract_if(v: HashSet<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let odd: Vec<_> = tree.extract_if(|x| x % 2 == 1).collect();
+        let mut w: Vec<_> = v.into_iter().collect();
+        w.sort();
+        odd.iter()
+            .copied()
+            .eq(w.iter().copied().filter(|x| x % 2 == 1))
+            && tree
+                .iter()
+                .copied()
+                .eq(w.iter().copied().filter(|x| x % 2 == 0))
+            && check_factors(&tree)
+            && check_sizes(&tree)
+            && check_height(tree)
+    }
+
+    #[test]
+    fn first_last() {
+        let tree: AVLTree<_> = vec![5, 1, 9, 3].into_iter().collect();
+        assert_eq!(tree.first(), Some(&1));
+        assert_eq!(tree.last(), Some(&9));
+        let empty: AVLTree<usize> = Empty;
+        assert_eq!(empty.first(), None);
+        assert_eq!(empty.last(), None);
+    }
+
+    #[test]
+    fn display() {
+        let tree: AVLTree<_> = vec![3, 1, 2].into_iter().collect();
+        assert_eq!(tree.to_string(), "{1, 2, 3}");
+        assert_eq!(AVLTree::<usize>::Empty.to_string(), "{}");
+    }
+
+    #[test]
+    fn debug_tree() {
+        let leaf = |value| {
+            NonEmpty(Box::new(Node {
+                value,
+                left: Empty,
+                right: Empty,
+                balance_factor: 0,
+                size: 1,
+            }))
+        };
+        let tree = NonEmpty(Box::new(Node {
+            value: 2,
+            left: leaf(1),
+            right: leaf(3),
+            balance_factor: 0,
+            size: 3,
+        }));
+        assert_eq!(tree.debug_tree(), "    3 (0)\n2 (0)\n    1 (0)\n");
+        let tree = NonEmpty(Box::new(Node {
+            value: 1,
+            left: Empty,
+            right: leaf(2),
+            balance_factor: 1,
+            size: 2,
+        }));
+        assert_eq!(tree.debug_tree(), "    2 (0)\n1 (1)\n");
+        assert_eq!(AVLTree::<usize>::Empty.debug_tree(), "");
+    }
+
+    fn hash_of<T: Hash>(v: &T) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[quickcheck]
+    fn hash(v: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let reversed: AVLTree<_> = v.iter().rev().copied().collect();
+        hash_of(&tree) == hash_of(&reversed)
+    }
+
+    #[test]
+    fn hash_as_key() {
+        let a: AVLTree<_> = vec![1, 2, 3, 4, 5].into_iter().collect();
+        let b: AVLTree<_> = vec![4, 2, 5, 1, 3].into_iter().collect();
+        let mut map = std::collections::HashMap::new();
+        map.insert(a, "a");
+        assert_eq!(map.get(&b), Some(&"a"));
+    }
+
+    #[quickcheck]
+    fn ord(v: HashSet<u8>, w: HashSet<u8>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let mut x: Vec<_> = v.into_iter().collect();
+        let mut y: Vec<_> = w.into_iter().collect();
+        x.sort();
+        y.sort();
+        a.cmp(&b) == x.cmp(&y) && a.partial_cmp(&b) == Some(x.cmp(&y))
+    }
+
+    #[test]
+    fn ord_prefix() {
+        let tree = |v: Vec<usize>| v.into_iter().collect::<AVLTree<_>>();
+        assert!(tree(vec![1, 2]) < tree(vec![1, 2, 3]));
+        assert!(tree(vec![]) < tree(vec![0]));
+        assert!(tree(vec![1, 3]) > tree(vec![1, 2, 4]));
+        assert_eq!(tree(vec![3, 1, 2]).cmp(&tree(vec![1, 2, 3])), Equal);
+        let mut trees = vec![tree(vec![2]), tree(vec![1, 5]), tree(vec![1])];
+        trees.sort();
+        assert_eq!(trees, vec![tree(vec![1]), tree(vec![1, 5]), tree(vec![2])]);
+    }
+
+    #[quickcheck]
+    fn height(v: Vec<usize>, pops: usize) -> bool {
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        for _ in 0..pops % 16 {
+            tree.pop_min();
+        }
+        tree.height() == tree.depth()
+    }
+
+    #[test]
+    fn clear() {
+        let mut tree: AVLTree<_> = (0..100).collect();
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert!(tree.is_empty());
+        assert!(tree.insert(1));
+    }
+
+    #[test]
+    fn from_sorted() {
+        for n in 0..300usize {
+            let tree = AVLTree::from_sorted((0..n).collect());
+            let min_depth = ((n + 1) as f64).log2().ceil() as usize;
+            assert!(tree.iter().copied().eq(0..n));
+            assert!(check_factors(&tree) && check_sizes(&tree));
+            assert_eq!(tree.depth(), min_depth);
+            assert!(check_height(tree));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn from_sorted_unsorted() {
+        AVLTree::from_sorted(vec![1, 3, 2]);
+    }
+
+    #[quickcheck]
+    fn from_sorted_iter(n: u16) -> bool {
+        let n = n as usize % 2000;
+        let tree = AVLTree::from_sorted_iter(0..n);
+        tree == AVLTree::from_sorted((0..n).collect())
+            && tree.height() == AVLTree::from_sorted((0..n).collect()).height()
+            && check_factors(&tree)
+            && check_sizes(&tree)
+    }
+
+    #[quickcheck]
+    fn count_range(v: HashSet<usize>, l: Option<usize>, r: Option<usize>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        tree.count_range(l.as_ref(), r.as_ref()) == tree.range(l.as_ref(), r.as_ref()).count()
+    }
+
+    type Links<T> = HashMap<*const Node<T>, (*const Node<T>, *const Node<T>)>;
+
+    fn links<T>(tree: &AVLTree<T>) -> Links<T> {
+        // child pointers of every node keyed by its address; the root pointer is keyed by null
+        fn ptr<T>(tree: &AVLTree<T>) -> *const Node<T> {
+            tree.as_node().map_or(core::ptr::null(), |node| node)
+        }
+        let mut links = HashMap::new();
+        links.insert(core::ptr::null(), (ptr(tree), core::ptr::null()));
+        let mut stack = vec![tree];
+        while let Some(tree) = stack.pop() {
+            if let NonEmpty(ref node) = *tree {
+                links.insert(&**node as *const _, (ptr(&node.left), ptr(&node.right)));
+                stack.push(&node.left);
+                stack.push(&node.right);
+            }
+        }
+        links
+    }
+
+    fn relinked<T>(before: &Links<T>, after: &Links<T>) -> usize {
+        // nodes never move in memory, so a plain insertion relinks only the new leaf's
+        // parent; a single or double rotation relinks at most four more
+        before
+            .iter()
+            .filter(|&(node, children)| after.get(node) != Some(children))
+            .count()
+    }
+
+    fn insert_relinked<T: Ord>(tree: &mut AVLTree<T>, x: T) -> usize {
+        let before = links(tree);
+        tree.insert(x);
+        relinked(&before, &links(tree))
+    }
+
+    #[quickcheck]
+    fn insert_rotations(v: Vec<usize>) -> bool {
+        let mut tree = Empty;
+        v.into_iter().all(|x| insert_relinked(&mut tree, x) <= 5)
+            && check_factors(&tree)
+            && check_sizes(&tree)
+    }
+
+    #[quickcheck]
+    fn insert_rotation_absorbs_growth(v: Vec<u8>) -> bool {
+        // rebalancing only happens where the height grew, and a rotation there
+        // restores the old height, so nothing above it needs fixing
+        let mut tree = Empty;
+        v.into_iter().all(|x| {
+            let (height, len) = (tree.height(), tree.len());
+            let rotated = insert_relinked(&mut tree, x) > 1;
+            let grown = tree.height() - height;
+            match tree.len() - len {
+                0 => !rotated && grown == 0,
+                _ => grown <= 1 && (!rotated || grown == 0),
+            }
+        }) && check_factors(&tree)
+    }
+
+    #[test]
+    fn insert_rotations_sorted() {
+        // sorted input rotates on about every other insertion, but never more than once each
+        let n = 2000;
+        let mut tree = Empty;
+        let mut before = links(&tree);
+        let mut total = 0;
+        for x in 0..n {
+            tree.insert(x);
+            let after = links(&tree);
+            total += relinked(&before, &after);
+            before = after;
+        }
+        assert!(total <= 4 * n);
+        assert!(check_factors(&tree));
+        assert!(check_height(tree));
+    }
+
+    #[test]
+    fn drop_deep() {
+        let mut tree = Empty;
+        for value in 0..100_000 {
+            tree = NonEmpty(Box::new(Node {
+                value,
+                left: tree,
+                right: Empty,
+                balance_factor: -1,
+                size: value + 1,
+            }));
+        }
+        drop(tree);
+        let tree: AVLTree<_> = (0..100_000).collect();
+        drop(tree);
+    }
+
+    fn count<T>(tree: &AVLTree<T>) -> usize {
+        match *tree {
+            Empty => 0,
+            NonEmpty(ref v) => 1 + count(&v.left) + count(&v.right),
+        }
+    }
+
+    #[quickcheck]
+    fn cached_len(ops: Vec<(u8, usize)>) -> bool {
+        let mut tree = Empty;
+        ops.into_iter().all(|(op, x)| {
+            match op % 4 {
+                0 | 1 => {
+                    tree.insert(x % 64);
+                }
+                2 => {
+                    tree.remove_by(&|v: &usize| (x % 64).cmp(v));
+                }
+                _ => {
+                    tree.pop_min();
+                }
+            }
+            tree.len() == count(&tree) && tree.is_empty() == (count(&tree) == 0)
+        })
+    }
+
+    #[quickcheck]
+    fn check_invariant(v: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        tree.check_invariant()
+    }
+
+    #[test]
+    fn check_invariant_corrupted() {
+        let mut tree: AVLTree<_> = (0..10).collect();
+        assert!(tree.check_invariant());
+        tree.node().balance_factor += 1;
+        assert!(!tree.check_invariant());
+        tree.node().balance_factor -= 1;
+        tree.node().value = 100;
+        assert!(!tree.check_invariant());
+        let mut tree: AVLTree<_> = (0..10).collect();
+        Boxed.rotate_left(&mut tree);
+        assert!(!tree.check_invariant());
+    }
+
+    #[quickcheck]
+    fn from_vec(v: Vec<usize>) -> bool {
+        let tree = AVLTree::from(v.clone());
+        let mut w = v;
+        w.sort();
+        w.dedup();
+        tree.iter().copied().eq(w.into_iter()) && tree.check_invariant()
+    }
+
+    #[test]
+    fn from_array() {
+        let tree = AVLTree::from([3, 1, 2, 3, 1]);
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(tree.check_invariant());
+        assert!(AVLTree::<usize>::from([]).is_empty());
+    }
+
+    #[quickcheck]
+    fn into_vec(v: Vec<usize>) -> bool {
+        let mut w = v.clone();
+        w.sort();
+        w.dedup();
+        Vec::from(AVLTree::from(v)) == w
+    }
+
+    #[test]
+    fn nearest_by() {
+        let tree = AVLTree::from(vec![10i64, 20, 30]);
+        let dist = |a: &i64, b: &i64| (a - b).abs();
+        assert_eq!(tree.nearest_by(&12, dist), Some(&10));
+        assert_eq!(tree.nearest_by(&18, dist), Some(&20));
+        assert_eq!(tree.nearest_by(&15, dist), Some(&10));
+        assert_eq!(tree.nearest_by(&25, dist), Some(&20));
+        assert_eq!(tree.nearest_by(&20, dist), Some(&20));
+        assert_eq!(tree.nearest_by(&-5, dist), Some(&10));
+        assert_eq!(tree.nearest_by(&99, dist), Some(&30));
+        assert_eq!(AVLTree::<i64>::Empty.nearest_by(&1, dist), None);
+    }
+
+    #[quickcheck]
+    fn nearest_by_linear(v: HashSet<i32>, key: i32) -> bool {
+        let tree: AVLTree<_> = v.iter().map(|&x| x as i64).collect();
+        let key = key as i64;
+        let dist = |a: &i64, b: &i64| (a - b).abs();
+        let mut w: Vec<_> = tree.iter().collect();
+        w.sort_by_key(|&&x| (dist(&key, &x), x));
+        tree.nearest_by(&key, dist) == w.first().copied()
+    }
+
+    #[quickcheck]
+    fn k_smallest_largest(v: HashSet<usize>, k: usize) -> bool {
+        let k = k % (v.len() + 2);
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.iter().collect();
+        w.sort();
+        let smallest = w[..k.min(w.len())].to_vec();
+        w.reverse();
+        let largest = w[..k.min(w.len())].to_vec();
+        tree.k_smallest(k) == smallest && tree.k_largest(k) == largest
+    }
+
+    #[quickcheck]
+    fn rangeiter_clone(v: HashSet<usize>, l: usize, r: usize, k: usize) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let all: Vec<_> = tree.range(Some(&l), Some(&r)).collect();
+        let k = k % (all.len() + 1);
+        let mut iter = tree.range(Some(&l), Some(&r));
+        for _ in 0..k {
+            iter.next();
+        }
+        let cloned = iter.clone();
+        let a: Vec<_> = iter.collect();
+        let b: Vec<_> = cloned.rev().collect::<Vec<_>>().into_iter().rev().collect();
+        a == all[k..] && b == all[k..]
     }
 
     #[quickcheck]
-    fn iter(v: HashSet<usize>) -> bool {
-        let mut v: Vec<_> = v.into_iter().collect();
-        v.sort();
-        let mut tree = Empty;
-        for &vi in v.iter() {
-            tree.add(vi);
+    fn rangeiter_size_hint(v: HashSet<usize>, l: usize, r: usize, back: Vec<bool>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let mut remaining = tree.range_bounds(l..=r).count();
+        let mut iter = tree.range_bounds(l..=r);
+        let mut back = back.into_iter().cycle();
+        loop {
+            if iter.size_hint() != (remaining, Some(remaining)) {
+                return false;
+            }
+            let item = if back.next().unwrap_or(false) {
+                iter.next_back()
+            } else {
+                iter.next()
+            };
+            if item.is_none() {
+                return remaining == 0;
+            }
+            remaining -= 1;
         }
-        let w: Vec<_> = tree.iter().collect();
-        (0..v.len()).all(|i| v[i] == *w[i])
     }
 
     #[quickcheck]
-    fn from_iter(v: HashSet<usize>) {
-        let n = v.len();
-        let w = v.clone();
-        let mut w: Vec<_> = w.into_iter().collect();
+    fn map_residues(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mapped = tree.map(|x| x % 5);
+        let expected: HashSet<_> = v.iter().map(|x| x % 5).collect();
+        mapped.len() == expected.len()
+            && mapped.iter().all(|x| expected.contains(x))
+            && mapped.check_invariant()
+    }
+
+    #[quickcheck]
+    fn filter_matches_source(v: HashSet<usize>, m: usize) -> bool {
+        let m = m % 7 + 1;
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let filtered = tree.filter(|x| x % m == 0);
+        let mut expected: Vec<_> = v.iter().copied().filter(|x| x % m == 0).collect();
+        expected.sort();
+        filtered.iter().copied().collect::<Vec<_>>() == expected
+            && filtered.check_invariant()
+            && tree.len() == v.len()
+    }
+
+    #[quickcheck]
+    fn partition_even_odd(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let (even, odd) = tree.clone().partition(|x| x % 2 == 0);
+        even.iter().all(|x| x % 2 == 0)
+            && odd.iter().all(|x| x % 2 == 1)
+            && even.check_invariant()
+            && odd.check_invariant()
+            && even.union(&odd) == tree
+    }
+
+    #[test]
+    fn cursor_steps_across_boundary() {
+        let tree: AVLTree<_> = (0..20).map(|x| x * 2).collect();
+        let mut cursor = tree.lower_bound(&7);
+        assert_eq!(cursor.peek_prev(), Some(&6));
+        assert_eq!(cursor.peek_next(), Some(&8));
+        cursor.move_next();
+        assert_eq!(cursor.peek_prev(), Some(&8));
+        assert_eq!(cursor.peek_next(), Some(&10));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.peek_prev(), Some(&4));
+        assert_eq!(cursor.peek_next(), Some(&6));
+
+        let mut cursor = tree.lower_bound(&100);
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(cursor.peek_prev(), Some(&38));
+        cursor.move_next();
+        assert_eq!(cursor.peek_prev(), Some(&38));
+        cursor.move_prev();
+        assert_eq!(cursor.peek_next(), Some(&38));
+
+        let mut cursor = tree.lower_bound(&0);
+        assert_eq!(cursor.peek_prev(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.peek_next(), Some(&0));
+    }
+
+    #[quickcheck]
+    fn cursor_walk(v: HashSet<usize>, key: usize) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let k = tree.rank(&key);
+        let sorted: Vec<_> = tree.iter().collect();
+        let mut cursor = tree.lower_bound(&key);
+        for &value in &sorted[k..] {
+            if cursor.peek_next() != Some(value) {
+                return false;
+            }
+            cursor.move_next();
+        }
+        if cursor.peek_next().is_some() {
+            return false;
+        }
+        for &value in sorted.iter().rev() {
+            if cursor.peek_prev() != Some(value) {
+                return false;
+            }
+            cursor.move_prev();
+        }
+        cursor.peek_prev().is_none() && cursor.peek_next() == sorted.first().copied()
+    }
+
+    #[quickcheck]
+    fn remove_range_band(v: HashSet<usize>, l: Option<usize>, r: Option<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let inside = |x: &usize| l.iter().all(|&l| l <= *x) && r.iter().all(|&r| *x < r);
+        let mut expected: Vec<_> = v.iter().copied().filter(|x| !inside(x)).collect();
+        expected.sort();
+        let removed = tree.remove_range(l.as_ref(), r.as_ref());
+        removed == v.len() - expected.len()
+            && tree.iter().copied().collect::<Vec<_>>() == expected
+            && tree.check_invariant()
+    }
+
+    #[quickcheck]
+    fn pop_nth_ends(v: HashSet<usize>) -> bool {
+        let mut a: AVLTree<_> = v.iter().copied().collect();
+        let mut b = a.clone();
+        while !a.is_empty() {
+            if a.pop_nth(0) != b.pop_min() || !a.check_invariant() {
+                return false;
+            }
+        }
+        let mut a: AVLTree<_> = v.iter().copied().collect();
+        let mut b = a.clone();
+        while !a.is_empty() {
+            let last = a.len() - 1;
+            if a.pop_nth(last) != b.pop_max() || !a.check_invariant() {
+                return false;
+            }
+        }
+        a.pop_nth(0).is_none()
+    }
+
+    #[quickcheck]
+    fn pop_nth_middle(v: HashSet<usize>, k: usize) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.into_iter().collect();
         w.sort();
+        let k = k % (w.len() + 1);
+        let expected = if k < w.len() { Some(w.remove(k)) } else { None };
+        tree.pop_nth(k) == expected
+            && tree.iter().copied().collect::<Vec<_>>() == w
+            && tree.check_invariant()
+            && check_sizes(&tree)
+    }
+
+    #[quickcheck]
+    fn fold_range_sum(v: HashSet<u32>, l: Option<u32>, r: Option<u32>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().map(u64::from).collect();
+        let (l, r) = (l.map(u64::from), r.map(u64::from));
+        let folded = tree.fold_range(l.as_ref(), r.as_ref(), 0, |acc, x| acc + x);
+        folded == tree.range(l.as_ref(), r.as_ref()).sum::<u64>()
+    }
+
+    #[quickcheck]
+    fn from_iter_checked_unique(v: HashSet<usize>) -> bool {
+        let tree = AVLTree::from_iter_checked(v.iter().copied()).unwrap();
+        tree == v.into_iter().collect()
+    }
+
+    #[test]
+    fn from_iter_checked_duplicate() {
+        let result = AVLTree::from_iter_checked(vec![3, 1, 4, 1, 5]);
+        assert_eq!(result, Err(DuplicateError(1)));
+        assert_eq!(format!("{}", DuplicateError(1)), "duplicate value: 1");
+    }
+
+    #[test]
+    fn insert_replace_returns_old() {
+        let mut tree = AVLTree::default();
+        assert!(tree.insert_replace(Keyed { key: 1, count: 0 }).is_none());
+        let old = tree.insert_replace(Keyed { key: 1, count: 5 }).unwrap();
+        assert_eq!(old.count, 0);
+        assert_eq!(tree.get(&1).unwrap().count, 5);
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn pairs_adjacent() {
+        let tree: AVLTree<_> = (0..5).collect();
+        let pairs: Vec<_> = tree.pairs().map(|(a, b)| (*a, *b)).collect();
+        assert_eq!(pairs, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+        let single: AVLTree<_> = (0..1).collect();
+        assert_eq!(single.pairs().count(), 0);
+        assert_eq!(AVLTree::<i32>::default().pairs().count(), 0);
+    }
+
+    #[quickcheck]
+    fn for_loop_by_ref(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut seen = Vec::new();
+        for x in &tree {
+            seen.push(*x);
+        }
+        seen == tree.iter().copied().collect::<Vec<_>>()
+    }
+
+    #[quickcheck]
+    fn for_loop_by_mut(v: HashSet<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().map(|&key| Keyed { key, count: 0 }).collect();
+        for x in &mut tree {
+            x.count = x.key * 2;
+        }
+        tree.iter().all(|x| x.count == x.key * 2) && tree.len() == v.len()
+    }
+
+    #[quickcheck]
+    fn into_iter_double_ended(v: HashSet<usize>, back: Vec<bool>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut iter = tree.into_iter();
+        let mut back = back.into_iter().cycle();
+        let mut front = Vec::new();
+        let mut rear = Vec::new();
+        loop {
+            if iter.len() != v.len() - front.len() - rear.len() {
+                return false;
+            }
+            let item = if back.next().unwrap_or(false) {
+                iter.next_back().map(|x| rear.push(x))
+            } else {
+                iter.next().map(|x| front.push(x))
+            };
+            if item.is_none() {
+                break;
+            }
+        }
+        let mut expected: Vec<_> = v.into_iter().collect();
+        expected.sort();
+        front.extend(rear.into_iter().rev());
+        front == expected
+    }
+
+    #[quickcheck]
+    fn get_full(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let w: Vec<_> = tree.iter().copied().collect();
+        w.iter()
+            .enumerate()
+            .all(|(i, x)| tree.get_full(x) == Some((i, x)) && tree.select(i) == Some(x))
+            && keys
+                .iter()
+                .all(|k| tree.get_full(k).map(|(_, x)| x) == tree.get(k))
+    }
+
+    #[test]
+    fn any_in_range_gaps() {
+        let tree: AVLTree<_> = (0..10).map(|x| x * 10).collect();
+        assert!(tree.any_in_range(Some(&0), Some(&1)));
+        assert!(tree.any_in_range(Some(&15), Some(&25)));
+        assert!(!tree.any_in_range(Some(&11), Some(&20)));
+        assert!(!tree.any_in_range(Some(&20), Some(&20)));
+        assert!(!tree.any_in_range(Some(&91), None));
+        assert!(tree.any_in_range(None, Some(&1)));
+        assert!(!AVLTree::<i32>::default().any_in_range::<i32>(None, None));
+    }
+
+    #[quickcheck]
+    fn any_in_range(v: HashSet<usize>, l: Option<usize>, r: Option<usize>) -> bool {
         let tree: AVLTree<_> = v.into_iter().collect();
-        assert_eq!(tree.len(), n);
-        for (i, a) in tree.into_iter().enumerate() {
-            assert_eq!(a, w[i]);
+        tree.any_in_range(l.as_ref(), r.as_ref()) == (tree.count_range(l.as_ref(), r.as_ref()) > 0)
+    }
+
+    #[test]
+    fn index_by_borrowed_key() {
+        let tree: AVLTree<String> = vec!["a".to_string(), "key".to_string()].into();
+        let key = String::from("key");
+        assert_eq!(tree[key.as_str()], "key");
+        assert_eq!(tree["a"], "a");
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_by_missing_key() {
+        let tree: AVLTree<String> = vec!["a".to_string()].into();
+        let _ = &tree["b"];
+    }
+
+    #[quickcheck]
+    fn rebalance_optimal(v: Vec<usize>, removed: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        for k in removed {
+            tree.pop_nth(k % (tree.len() + 1));
+        }
+        let before: Vec<_> = tree.iter().copied().collect();
+        tree.rebalance_optimal();
+        let optimal = (usize::BITS - tree.len().leading_zeros()) as usize;
+        tree.height() == optimal
+            && tree.iter().copied().collect::<Vec<_>>() == before
+            && tree.check_invariant()
+    }
+
+    #[test]
+    fn peek_min_max() {
+        let mut tree: AVLTree<_> = vec![3, 1, 2].into();
+        assert_eq!(tree.peek_min(), Some(&1));
+        assert_eq!(tree.peek_max(), Some(&3));
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.pop_min(), Some(1));
+        assert_eq!(tree.peek_min(), Some(&2));
+        assert_eq!(AVLTree::<i32>::default().peek_max(), None);
+    }
+
+    #[test]
+    fn new_is_empty() {
+        const TREE: AVLTree<i32> = AVLTree::new();
+        assert!(TREE.is_empty());
+        assert!(AVLTree::<i32>::new().is_empty());
+    }
+
+    #[test]
+    fn join_disjoint() {
+        let a: AVLTree<_> = (0..100).collect();
+        let b: AVLTree<_> = (100..200).collect();
+        let tree = a.join(b);
+        assert!(tree.iter().copied().eq(0..200));
+        assert!(tree.check_invariant());
+    }
+
+    #[quickcheck]
+    fn join_uneven(n: usize, m: usize) -> bool {
+        let (n, m) = (n % 300, m % 300);
+        let a = AVLTree::from_sorted_iter(0..n);
+        let b: AVLTree<_> = (n..n + m).collect();
+        let tree = a.join(b);
+        tree.iter().copied().eq(0..n + m) && tree.check_invariant() && check_sizes(&tree)
+    }
+
+    #[test]
+    fn take_returns_payload() {
+        let mut tree: AVLTree<_> = (0..10)
+            .map(|key| Keyed {
+                key,
+                count: key * 3,
+            })
+            .collect();
+        let taken = tree.take(&4).unwrap();
+        assert_eq!((taken.key, taken.count), (4, 12));
+        assert_eq!(tree.len(), 9);
+        assert!(!tree.contains(&4));
+        assert!(tree.take(&4).is_none());
+    }
+
+    #[quickcheck]
+    fn take(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let mut expected = v;
+        keys.iter().all(|k| {
+            tree.take(k) == expected.take(k) && tree.check_invariant() && check_sizes(&tree)
+        }) && tree.len() == expected.len()
+    }
+
+    #[quickcheck]
+    fn retain_count(v: HashSet<usize>, m: usize) -> bool {
+        let m = m % 5 + 1;
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let old_len = tree.len();
+        let removed = tree.retain_count(|x| x % m != 0);
+        removed == old_len - tree.len() && removed == v.iter().filter(|&x| x % m == 0).count()
+    }
+
+    #[quickcheck]
+    fn enumerate_iter(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        tree.enumerate_iter().map(|(i, _)| i).eq(0..tree.len())
+            && tree
+                .enumerate_iter()
+                .all(|(i, x)| tree.select(i) == Some(x) && tree.rank(x) == i)
+    }
+
+    #[quickcheck]
+    fn split_at_rank(v: HashSet<usize>, k: usize) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let all: Vec<_> = tree.iter().copied().collect();
+        let n = all.len();
+        let k = k % (n + 2);
+        let rest = tree.split_at_rank(k);
+        let k = k.min(n);
+        tree.len() == k
+            && rest.len() == n - k
+            && tree.iter().copied().eq(all[..k].iter().copied())
+            && rest.iter().copied().eq(all[k..].iter().copied())
+            && tree.check_invariant()
+            && rest.check_invariant()
+    }
+
+    #[quickcheck]
+    fn split_heights(v: HashSet<usize>, key: usize, k: usize) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let h = tree.height();
+        let ((l, hl), (r, hr)) = Boxed.split_at_height(tree.clone(), h, &key);
+        let ((a, ha), (b, hb)) = Boxed.split_rank_at_height(tree, h, k % (v.len() + 1));
+        (l.depth(), r.depth(), a.depth(), b.depth()) == (hl, hr, ha, hb)
+    }
+
+    #[test]
+    fn split_at_median() {
+        let mut tree: AVLTree<_> = (0..101).collect();
+        let rest = tree.split_at_rank(50);
+        assert_eq!((tree.len(), rest.len()), (50, 51));
+        assert_eq!(rest.first(), Some(&50));
+    }
+
+    #[quickcheck]
+    fn bulk_insert(v: Vec<u8>, w: Vec<u8>) -> bool {
+        let mut tree: AVLTree<_> = v
+            .iter()
+            .map(|&key| Keyed {
+                key: key as usize,
+                count: 0,
+            })
+            .collect();
+        let mut expected = tree.clone();
+        let items: Vec<_> = w
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| Keyed {
+                key: key as usize,
+                count: i + 1,
+            })
+            .collect();
+        for x in items.iter().cloned() {
+            expected.insert(x);
         }
+        tree.bulk_insert(items);
+        tree.iter()
+            .map(|x| (x.key, x.count))
+            .eq(expected.iter().map(|x| (x.key, x.count)))
+            && tree.check_invariant()
     }
 
     #[quickcheck]
-    fn duplicates(v: Vec<i64>) -> bool {
-        let tree = v.into_iter().collect::<AVLTree<_>>();
-        let mut v: Vec<_> = tree.into_iter().collect();
-        let n = v.len();
-        v.dedup();
-        n == v.len()
+    fn range_reversed_is_empty(v: HashSet<usize>, l: usize, r: usize) -> bool {
+        let (l, r) = if l < r { (r, l) } else { (l, r) };
+        let tree: AVLTree<_> = v.into_iter().chain(r..=l).collect();
+        tree.range(Some(&l), Some(&r)).next().is_none()
+            && tree.range(Some(&l), Some(&r)).len() == 0
+            && tree.range(Some(&l), Some(&r)).next_back().is_none()
+            && tree
+                .range_bounds((Bound::Excluded(l), Bound::Excluded(l)))
+                .next()
+                .is_none()
+            && tree.count_range(Some(&l), Some(&r)) == 0
+    }
+
+    #[test]
+    fn range_reversed() {
+        let tree: AVLTree<_> = (0..20).collect();
+        let (l, r) = (10, 5);
+        assert_eq!(tree.range(Some(&l), Some(&r)).count(), 0);
+        assert_eq!(tree.range_bounds(l..r).count(), 0);
+        assert_eq!(tree.range_bounds(l..=r).count(), 0);
     }
 
     #[quickcheck]
-    fn get_and_index(v: HashSet<usize>, indices: Vec<usize>) -> bool {
-        let w = v.clone();
+    fn range_mut(v: HashSet<usize>, l: Option<usize>, r: Option<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().map(|&key| Keyed { key, count: 0 }).collect();
+        let n = tree.range_mut(l.as_ref(), r.as_ref()).len();
+        for x in tree.range_mut(l.as_ref(), r.as_ref()) {
+            x.count += 1;
+        }
+        let inside = |x: usize| l.iter().all(|&l| l <= x) && r.iter().all(|&r| x < r);
+        n == tree.count_range(l.as_ref(), r.as_ref())
+            && tree.iter().all(|x| x.count == inside(x.key) as usize)
+    }
+
+    #[quickcheck]
+    fn range_owned(v: HashSet<usize>) -> bool {
         let tree: AVLTree<_> = v.into_iter().collect();
-        w.iter().all(|wi| tree.get(wi) != None)
-            && indices.iter().all(|i| w.get(i) == tree.get(i))
-            && w.iter().all(|wi| tree[wi] == *wi)
+        let n = tree.len();
+        tree.range_owned(Some(n / 4), Some(n * 3 / 4))
+            .eq(tree.range(Some(&(n / 4)), Some(&(n * 3 / 4))))
+            && tree.range_owned(None, Some(n + 1)).count() == tree.count_range(None, Some(&(n + 1)))
     }
 
     #[quickcheck]
-    fn rangeiter(v: HashSet<usize>, l: usize, r: usize) -> bool {
-        let (l, r) = if l < r { (l, r) } else { (r, l) };
-        let mut v: Vec<_> = v.into_iter().collect();
-        v.sort();
-        let tree: AVLTree<_> = v.iter().map(|&x| x).collect();
-        let li = v.binary_search(&l).unwrap_or_else(|x| x);
-        let ri = v.binary_search(&r).unwrap_or_else(|x| x);
-        let w: Vec<_> = tree.range(Some(&l), Some(&r)).collect();
-        (0..(ri - li)).all(|i| v[i + li] == *w[i])
+    fn merge_from(v: HashSet<u8>, w: Vec<u8>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let absent: HashSet<_> = w.iter().filter(|x| !v.contains(x)).collect();
+        tree.merge_from(w.iter().copied()) == absent.len() && tree.len() == v.len() + absent.len()
+    }
+
+    #[quickcheck]
+    fn recompute_balance_factors(v: HashSet<usize>, seed: Vec<i8>) -> bool {
+        fn corrupt<T>(tree: &mut AVLTree<T>, seed: &mut impl Iterator<Item = i8>) {
+            if let NonEmpty(ref mut node) = *tree {
+                node.balance_factor = seed.next().unwrap_or(0) % 3;
+                corrupt(&mut node.left, seed);
+                corrupt(&mut node.right, seed);
+            }
+        }
+        let mut tree: AVLTree<_> = v.into_iter().collect();
+        corrupt(&mut tree, &mut seed.into_iter().cycle());
+        tree.recompute_balance_factors();
+        tree.check_invariant()
+    }
+
+    #[test]
+    fn recompute_after_graft() {
+        // moves the left subtree of `b`, holding 3, under `a`'s largest value
+        let mut a: AVLTree<_> = (0..3).collect();
+        let mut b: AVLTree<_> = (3..7).collect();
+        let graft = mem::take(&mut b.node().left);
+        let mut tree = &mut a;
+        while let NonEmpty(ref mut node) = *tree {
+            tree = &mut node.right;
+        }
+        *tree = graft;
+        assert!(!a.check_invariant());
+        a.recompute_balance_factors();
+        assert_eq!(a.len(), 4);
+        assert!(a.iter().copied().eq(0..4));
+        assert!(a.check_invariant());
+    }
+
+    #[quickcheck]
+    fn retain_intersection(v: HashSet<u8>, w: HashSet<u8>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        tree.retain_intersection(&w.iter().copied().collect());
+        let mut expected = v;
+        expected.retain(|x| w.contains(x));
+        tree.len() == expected.len() && tree.iter().all(|x| expected.contains(x))
+    }
+
+    #[quickcheck]
+    fn remove_all(v: HashSet<u8>, w: HashSet<u8>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        tree.remove_all(&w.iter().copied().collect());
+        let mut expected = v;
+        expected.retain(|x| !w.contains(x));
+        tree.len() == expected.len() && tree.iter().all(|x| expected.contains(x))
+    }
+
+    #[quickcheck]
+    fn min_max_by(v: HashMap<usize, usize>) -> bool {
+        let tree: AVLTree<_> = v
+            .iter()
+            .map(|(&key, &count)| Keyed { key, count })
+            .collect();
+        let min = tree.min_by(|a, b| a.count.cmp(&b.count)).map(|x| x.count);
+        let max = tree.max_by(|a, b| a.count.cmp(&b.count)).map(|x| x.count);
+        min == v.values().min().copied() && max == v.values().max().copied()
+    }
+
+    #[quickcheck]
+    fn iter_rev(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.into_iter().collect();
+        let mut forward: Vec<_> = tree.iter().collect();
+        forward.reverse();
+        tree.iter_rev().eq(forward.iter().copied()) && tree.iter_rev().rev().eq(tree.iter())
+    }
+
+    #[quickcheck]
+    fn position(v: HashSet<usize>, keys: Vec<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut w: Vec<_> = v.iter().copied().collect();
+        w.sort();
+        w.iter()
+            .enumerate()
+            .all(|(i, x)| tree.position(x) == Some(i))
+            && keys
+                .iter()
+                .all(|k| tree.position(k) == w.binary_search(k).ok())
+    }
+
+    #[quickcheck]
+    fn remove_rank_drains(v: HashSet<usize>, ranks: Vec<usize>) -> bool {
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let mut ranks = ranks.into_iter().chain(std::iter::repeat(0));
+        let mut drained = Vec::new();
+        while !tree.is_empty() {
+            let k = ranks.next().unwrap() % tree.len();
+            let expected = tree.select(k).copied();
+            let removed = tree.remove_rank(k);
+            if removed != expected || !tree.check_invariant() {
+                return false;
+            }
+            drained.extend(removed);
+        }
+        let mut expected: Vec<_> = v.into_iter().collect();
+        drained.sort();
+        expected.sort();
+        drained == expected && tree.remove_rank(0).is_none()
+    }
+
+    #[test]
+    fn try_from_sorted() {
+        let tree = AVLTree::try_from_sorted(vec![1, 2, 5, 9]).unwrap();
+        assert!(tree.iter().copied().eq(vec![1, 2, 5, 9]));
+        assert!(AVLTree::try_from_sorted(Vec::<i32>::new())
+            .unwrap()
+            .is_empty());
+        assert_eq!(AVLTree::try_from_sorted(vec![1, 2, 5, 3, 9]).err(), Some(3));
+        assert_eq!(AVLTree::try_from_sorted(vec![1, 2, 2, 3]).err(), Some(2));
+        assert_eq!(AVLTree::try_from_sorted(vec![4, 1]).err(), Some(1));
+    }
+
+    #[test]
+    fn as_node() {
+        let tree: AVLTree<_> = vec![1, 2, 3].into();
+        let root = tree.as_node().unwrap();
+        assert_eq!(root.value, 2);
+        assert_eq!(root.left.as_node().map(|n| n.value), Some(1));
+        assert_eq!(root.right.as_node().map(|n| n.value), Some(3));
+        assert!(AVLTree::<i32>::new().as_node().is_none());
+    }
+
+    #[test]
+    fn node_balance_factor() {
+        let mut tree = AVLTree::new();
+        tree.insert(1);
+        tree.insert(2);
+        assert_eq!(tree.as_node().unwrap().balance_factor(), 1);
+        tree.insert(0);
+        let root = tree.as_node().unwrap();
+        assert_eq!(root.balance_factor(), 0);
+        tree = vec![1, 2].into();
+        assert_eq!(tree.as_node().unwrap().balance_factor(), -1);
+        tree = (0..3).rev().collect();
+        let root = tree.as_node().unwrap();
+        assert_eq!((root.value, root.balance_factor()), (1, 0));
+    }
+
+    #[test]
+    fn get_disjoint_mut_swap() {
+        let mut tree: AVLTree<_> = (0..10)
+            .map(|key| Keyed {
+                key,
+                count: key * 10,
+            })
+            .collect();
+        match tree.get_disjoint_mut(&2, &7) {
+            (Some(a), Some(b)) => mem::swap(&mut a.count, &mut b.count),
+            _ => panic!("both keys are present"),
+        }
+        assert_eq!(tree.get(&2).unwrap().count, 70);
+        assert_eq!(tree.get(&7).unwrap().count, 20);
+        let (a, b) = tree.get_disjoint_mut(&3, &42);
+        assert_eq!((a.map(|x| x.key), b.map(|x| x.key)), (Some(3), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "get_disjoint_mut called with equal keys")]
+    fn get_disjoint_mut_same_key() {
+        let mut tree: AVLTree<_> = (0..10).collect();
+        let _ = tree.get_disjoint_mut(&3, &3);
+    }
+
+    #[quickcheck]
+    fn get_disjoint_mut(v: HashSet<usize>, a: usize, b: usize) -> bool {
+        if a == b {
+            return true;
+        }
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let (x, y) = tree.get_disjoint_mut(&a, &b);
+        x.copied() == v.get(&a).copied() && y.copied() == v.get(&b).copied()
+    }
+
+    #[quickcheck]
+    fn remove_if(v: HashSet<usize>, m: usize) -> bool {
+        let m = m % 10;
+        let mut tree: AVLTree<_> = v.iter().copied().collect();
+        let mut calls = 0;
+        tree.remove_if(|x| {
+            calls += 1;
+            x % 10 < m
+        });
+        let mut expected: Vec<_> = v.iter().copied().filter(|x| x % 10 >= m).collect();
+        expected.sort();
+        calls == v.len()
+            && tree.iter().copied().eq(expected)
+            && tree.check_invariant()
+            && check_sizes(&tree)
+    }
+
+    #[test]
+    fn remove_if_most() {
+        let mut tree: AVLTree<_> = (0..1000).collect();
+        tree.remove_if(|x| x % 10 < 7);
+        assert!(tree.iter().copied().eq((0..1000).filter(|x| x % 10 >= 7)));
+        assert!(tree.check_invariant());
+    }
+
+    #[quickcheck]
+    fn to_vec(v: HashSet<usize>) -> bool {
+        let tree: AVLTree<_> = v.iter().copied().collect();
+        let mut expected: Vec<_> = v.into_iter().collect();
+        expected.sort();
+        tree.to_vec().into_iter().copied().eq(expected)
+    }
+
+    #[quickcheck]
+    fn intersection_len(v: HashSet<u8>, w: HashSet<u8>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        a.intersection_len(&b) == v.intersection(&w).count()
+    }
+
+    #[quickcheck]
+    fn union_symmetric_difference_len(v: HashSet<u8>, w: HashSet<u8>) -> bool {
+        let a: AVLTree<_> = v.iter().copied().collect();
+        let b: AVLTree<_> = w.iter().copied().collect();
+        let both = a.intersection_len(&b);
+        a.union_len(&b) == a.len() + b.len() - both
+            && a.symmetric_difference_len(&b) == a.union_len(&b) - both
+            && a.union_len(&b) == v.union(&w).count()
+            && a.symmetric_difference_len(&b) == v.symmetric_difference(&w).count()
     }
 }
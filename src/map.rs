@@ -0,0 +1,249 @@
+//! Ordered map built on top of `AVLTree`
+use super::store::Boxed;
+use super::{AVLTree, RangeIter};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::{FromIterator, FusedIterator};
+use core::mem;
+use core::ops::RangeBounds;
+
+#[derive(Debug, Clone)]
+struct Pair<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: PartialEq, V> PartialEq for Pair<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Eq, V> Eq for Pair<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Pair<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Pair<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K, V> Borrow<K> for Pair<K, V> {
+    fn borrow(&self) -> &K {
+        &self.key
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AVLMap<K, V> {
+    tree: AVLTree<Pair<K, V>>,
+}
+
+impl<K, V> Default for AVLMap<K, V> {
+    fn default() -> Self {
+        AVLMap {
+            tree: AVLTree::Empty,
+        }
+    }
+}
+
+impl<K, V> AVLMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+impl<K: Ord, V> AVLMap<K, V> {
+    /// Inserts a key-value pair, returning the previous value stored for `key`.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(pair) = self.tree.find_by_mut(&|p: &Pair<K, V>| key.cmp(&p.key)) {
+            return Some(mem::replace(&mut pair.value, value));
+        }
+        self.tree.insert(Pair { key, value });
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.tree
+            .find_by(&|p: &Pair<K, V>| key.cmp(p.key.borrow()))
+            .map(|p| &p.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.tree
+            .find_by_mut(&|p: &Pair<K, V>| key.cmp(p.key.borrow()))
+            .map(|p| &mut p.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.tree
+            .remove_by(&|p: &Pair<K, V>| key.cmp(p.key.borrow()))
+            .0
+            .map(|p| p.value)
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range {
+            iter: RangeIter::new(&Boxed, &self.tree, range.start_bound(), range.end_bound()),
+        }
+    }
+}
+
+/// An iterator over the entries of an [`AVLMap`] in key order, created by
+/// [`iter`](AVLMap::iter) and [`range`](AVLMap::range).
+pub struct Range<'a, K, V> {
+    iter: RangeIter<'a, Pair<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|p| (&p.key, &p.value))
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|p| (&p.key, &p.value))
+    }
+}
+
+impl<'a, K: Ord, V> FusedIterator for Range<'a, K, V> {}
+
+impl<'a, K: Ord, V> IntoIterator for &'a AVLMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Range<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for AVLMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = AVLMap::new();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{check_factors, check_height, check_sizes};
+    use std::collections::HashMap;
+
+    #[quickcheck]
+    fn insert_and_get(v: Vec<(usize, u32)>) -> bool {
+        let map: AVLMap<_, _> = v.iter().copied().collect();
+        let expected: HashMap<_, _> = v.into_iter().collect();
+        map.len() == expected.len()
+            && expected.iter().all(|(k, v)| map.get(k) == Some(v))
+            && check_factors(&map.tree)
+            && check_height(map.tree)
+    }
+
+    #[quickcheck]
+    fn insert_returns_previous(v: Vec<(u8, u32)>) -> bool {
+        let mut map = AVLMap::new();
+        let mut expected = HashMap::new();
+        v.into_iter()
+            .all(|(k, v)| map.insert(k, v) == expected.insert(k, v))
+    }
+
+    #[quickcheck]
+    fn iter(v: HashMap<usize, u32>) -> bool {
+        let map: AVLMap<_, _> = v.iter().map(|(&k, &v)| (k, v)).collect();
+        let mut w: Vec<_> = v.iter().collect();
+        w.sort();
+        map.iter().eq(w.iter().copied()) && map.iter().rev().eq(w.iter().rev().copied())
+    }
+
+    #[quickcheck]
+    fn get_mut(v: HashMap<usize, u32>, keys: Vec<usize>) -> bool {
+        let mut map: AVLMap<_, _> = v.iter().map(|(&k, &v)| (k, v as u64)).collect();
+        for k in keys.iter() {
+            if let Some(x) = map.get_mut(k) {
+                *x += 1;
+            }
+        }
+        v.iter().all(|(k, &x)| {
+            map.get(k) == Some(&(x as u64 + keys.iter().filter(|&i| i == k).count() as u64))
+        }) && keys
+            .iter()
+            .all(|k| map.contains_key(k) == v.contains_key(k))
+    }
+
+    #[quickcheck]
+    fn remove(v: HashMap<usize, u32>, keys: Vec<usize>) -> bool {
+        let mut map: AVLMap<_, _> = v.iter().map(|(&k, &v)| (k, v)).collect();
+        let mut expected = v;
+        for k in keys.iter() {
+            if map.remove(k) != expected.remove(k) {
+                return false;
+            }
+            if !check_factors(&map.tree) || !check_sizes(&map.tree) {
+                return false;
+            }
+        }
+        map.len() == expected.len() && expected.iter().all(|(k, v)| map.get(k) == Some(v))
+    }
+
+    #[quickcheck]
+    fn range(v: HashMap<usize, u32>, l: usize, r: usize) -> bool {
+        let (l, r) = if l < r { (l, r) } else { (r, l) };
+        let map: AVLMap<_, _> = v.iter().map(|(&k, &v)| (k, v)).collect();
+        let mut w: Vec<_> = v.iter().filter(|(&k, _)| l <= k && k < r).collect();
+        w.sort();
+        map.range(l..r).eq(w.into_iter())
+    }
+
+    #[test]
+    fn string_keys() {
+        let mut map = AVLMap::new();
+        map.insert("b".to_string(), 2);
+        map.insert("a".to_string(), 1);
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.remove("b"), Some(2));
+        assert_eq!(map.get("b"), None);
+        assert_eq!(map.len(), 1);
+    }
+}
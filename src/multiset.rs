@@ -0,0 +1,183 @@
+//! Ordered multiset built on top of `AVLMap`
+use super::map::{AVLMap, Range};
+use core::borrow::Borrow;
+use core::iter::{FromIterator, FusedIterator};
+
+/// A sorted collection that keeps a count for each distinct value.
+///
+/// [`len`](AVLMultiSet::len) counts every copy, while
+/// [`distinct_len`](AVLMultiSet::distinct_len) counts each distinct value once.
+#[derive(Debug, Clone)]
+pub struct AVLMultiSet<T> {
+    map: AVLMap<T, usize>,
+    len: usize,
+}
+
+impl<T> Default for AVLMultiSet<T> {
+    fn default() -> Self {
+        AVLMultiSet {
+            map: AVLMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> AVLMultiSet<T> {
+    /// Creates an empty multiset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of values, counting duplicates.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of distinct values.
+    pub fn distinct_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns whether the multiset holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Ord> AVLMultiSet<T> {
+    /// Adds one copy of `value`, returning the new count.
+    pub fn insert(&mut self, value: T) -> usize {
+        self.len += 1;
+        if let Some(count) = self.map.get_mut(&value) {
+            *count += 1;
+            return *count;
+        }
+        self.map.insert(value, 1);
+        1
+    }
+
+    /// Removes one copy of `value`, returning whether it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match self.map.get_mut(value) {
+            None => return false,
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.map.remove(value);
+            }
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Returns how many copies of `value` are stored.
+    pub fn count<Q>(&self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.map.get(value).copied().unwrap_or(0)
+    }
+
+    /// Returns whether at least one copy of `value` is stored.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns an iterator over the distinct values and their counts in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            iter: self.map.iter(),
+        }
+    }
+}
+
+/// An iterator over the distinct values of an [`AVLMultiSet`] and their counts, created by
+/// [`iter`](AVLMultiSet::iter).
+pub struct Iter<'a, T> {
+    iter: Range<'a, T, usize>,
+}
+
+impl<'a, T: Ord> Iterator for Iter<'a, T> {
+    type Item = (&'a T, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(v, &n)| (v, n))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(v, &n)| (v, n))
+    }
+}
+
+impl<'a, T: Ord> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLMultiSet<T> {
+    type Item = (&'a T, usize);
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLMultiSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = AVLMultiSet::new();
+        for v in iter {
+            set.insert(v);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn insert_same_value() {
+        let mut set = AVLMultiSet::new();
+        for i in 0..5 {
+            assert_eq!(set.insert(7), i + 1);
+        }
+        set.insert(3);
+        assert_eq!(set.count(&7), 5);
+        assert_eq!(set.count(&3), 1);
+        assert_eq!(set.count(&4), 0);
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.distinct_len(), 2);
+    }
+
+    #[quickcheck]
+    fn counts(v: Vec<u8>, removed: Vec<u8>) -> bool {
+        let mut set: AVLMultiSet<_> = v.iter().copied().collect();
+        let mut expected = BTreeMap::new();
+        for &x in v.iter() {
+            *expected.entry(x).or_insert(0) += 1;
+        }
+        for x in removed.iter() {
+            let present = expected.contains_key(x);
+            if set.remove(x) != present {
+                return false;
+            }
+            if present {
+                *expected.get_mut(x).unwrap() -= 1;
+                if expected[x] == 0 {
+                    expected.remove(x);
+                }
+            }
+        }
+        set.len() == expected.values().sum::<usize>()
+            && set.distinct_len() == expected.len()
+            && set.iter().eq(expected.iter().map(|(k, &n)| (k, n)))
+    }
+}
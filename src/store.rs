@@ -0,0 +1,859 @@
+//! Node storage, and the balancing code shared by every kind of storage
+use super::{AVLTree, Node};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering::{self, *};
+use core::mem;
+
+/// Where the nodes of a tree live. Subtrees are reached through links: a [`Boxed`] link owns
+/// its node, while a link into [`Nodes`](crate::arena::Nodes) is an index into one vector.
+///
+/// The code below never holds on to a node while working on one of its children. It detaches
+/// the child's link first (see [`with_child`](Store::with_child)), so the same code serves
+/// stores that have to be borrowed to reach a node.
+pub trait Store<T>: Sized {
+    /// A link to a subtree; the default link is the empty tree.
+    type Link: Default;
+
+    fn node<'a>(&'a self, tree: &'a Self::Link) -> Option<&'a Node<T, Self::Link>>;
+
+    fn node_mut<'a>(&'a mut self, tree: &'a mut Self::Link) -> Option<&'a mut Node<T, Self::Link>>;
+
+    /// Stores `node` and returns a link to it.
+    fn alloc(&mut self, node: Node<T, Self::Link>) -> Self::Link;
+
+    /// Takes the root node of `tree` out of the store.
+    fn free(&mut self, tree: Self::Link) -> Option<Node<T, Self::Link>>;
+
+    /// Makes room for `additional` more nodes.
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn node_at<'a>(&'a mut self, tree: &'a mut Self::Link) -> &'a mut Node<T, Self::Link> {
+        match self.node_mut(tree) {
+            None => panic!("call on empty tree"),
+            Some(node) => node,
+        }
+    }
+
+    fn child<'a>(&'a self, tree: &'a Self::Link, ord: Ordering) -> &'a Self::Link
+    where
+        T: 'a,
+    {
+        match self.node(tree) {
+            None => panic!("call on empty tree"),
+            Some(node) => node.child(ord),
+        }
+    }
+
+    fn factor(&self, tree: &Self::Link) -> i8 {
+        match self.node(tree) {
+            None => panic!("call on empty tree"),
+            Some(node) => node.balance_factor,
+        }
+    }
+
+    /// Runs `f` on the left (`Less`) or right child of `tree`.
+    fn with_child<R, F>(&mut self, tree: &mut Self::Link, ord: Ordering, f: F) -> R
+    where
+        F: FnOnce(&mut Self, &mut Self::Link) -> R,
+    {
+        // the child is detached for the duration, so that `f` can borrow the store as well
+        let mut child = mem::take(self.node_at(tree).child_mut(ord));
+        let ret = f(self, &mut child);
+        *self.node_at(tree).child_mut(ord) = child;
+        ret
+    }
+
+    fn len(&self, tree: &Self::Link) -> usize {
+        self.node(tree).map_or(0, |node| node.size)
+    }
+
+    fn height<'a>(&'a self, mut tree: &'a Self::Link) -> usize
+    where
+        T: 'a,
+    {
+        // follows the taller child at each node
+        let mut height = 0;
+        while let Some(node) = self.node(tree) {
+            height += 1;
+            tree = if node.balance_factor > 0 {
+                &node.right
+            } else {
+                &node.left
+            };
+        }
+        height
+    }
+
+    fn update_size(&mut self, tree: &mut Self::Link) {
+        if let Some(node) = self.node(tree) {
+            let size = 1 + self.len(&node.left) + self.len(&node.right);
+            self.node_at(tree).size = size;
+        }
+    }
+
+    /// Builds a balanced tree from a strictly increasing iterator in O(n).
+    fn build<I: ExactSizeIterator<Item = T>>(&mut self, mut iter: I) -> Self::Link {
+        let n = iter.len();
+        self.reserve(n);
+        self.build_sorted(&mut iter, n).0
+    }
+
+    fn build_sorted<I: Iterator<Item = T>>(
+        &mut self,
+        iter: &mut I,
+        n: usize,
+    ) -> (Self::Link, usize) {
+        // builds a balanced tree from the next `n` values of a strictly increasing iterator
+        // returns: (tree, depth)
+        if n == 0 {
+            return (Self::Link::default(), 0);
+        }
+        let (left, ld) = self.build_sorted(iter, n / 2);
+        let value = iter.next().unwrap();
+        let (right, rd) = self.build_sorted(iter, n - n / 2 - 1);
+        let node = Node {
+            value,
+            left,
+            right,
+            balance_factor: rd as i8 - ld as i8,
+            size: n,
+        };
+        (self.alloc(node), ld + 1)
+    }
+
+    /// Moves the values of `tree` onto `out` in sorted order, emptying the store of its nodes.
+    fn drain_sorted(&mut self, tree: Self::Link, out: &mut Vec<T>) {
+        if let Some(node) = self.free(tree) {
+            self.drain_sorted(node.left, out);
+            out.push(node.value);
+            self.drain_sorted(node.right, out);
+        }
+    }
+
+    fn add_by<C>(&mut self, tree: &mut Self::Link, value: T, cmp: &C) -> bool
+    where
+        C: Fn(&T, &T) -> Ordering,
+    {
+        // First descent: record the path and the deepest node with a nonzero balance
+        // factor. Only that node can become unbalanced; the nodes below it all get
+        // deeper by one level, and the nodes above it keep their heights.
+        let mut path = Vec::new();
+        let mut critical = 0;
+        let mut t = &*tree;
+        while let Some(node) = self.node(t) {
+            let ord = cmp(&value, &node.value);
+            if ord == Equal {
+                return false;
+            }
+            if node.balance_factor != 0 {
+                critical = path.len();
+            }
+            path.push(ord);
+            t = node.child(ord);
+        }
+
+        // Second descent: unlink each subtree from its parent on the way down, fixing sizes
+        // along the whole path and balance factors from the critical node down. Then attach
+        // the new leaf and relink bottom-up, rebalancing only at the critical node.
+        let mut parents = Vec::with_capacity(path.len());
+        let mut t = mem::take(tree);
+        for (i, &ord) in path.iter().enumerate() {
+            let node = self.node_at(&mut t);
+            node.size += 1;
+            let d = if ord == Less { -1 } else { 1 };
+            if i == critical {
+                node.balance_factor += d;
+            } else if i > critical {
+                node.balance_factor = d;
+            }
+            let child = mem::take(node.child_mut(ord));
+            parents.push(t);
+            t = child;
+        }
+        t = self.alloc(Node {
+            value,
+            left: Self::Link::default(),
+            right: Self::Link::default(),
+            balance_factor: 0,
+            size: 1,
+        });
+        for (i, &ord) in path.iter().enumerate().rev() {
+            let mut parent = parents.pop().unwrap();
+            *self.node_at(&mut parent).child_mut(ord) = t;
+            if i == critical {
+                self.balance(&mut parent);
+            }
+            t = parent;
+        }
+        *tree = t;
+        true
+    }
+
+    fn remove_min(&mut self, tree: &mut Self::Link) -> (Option<T>, bool) {
+        // returns: (removed, shallowed)
+        let has_left = match self.node(tree) {
+            None => return (None, false),
+            Some(node) => self.node(&node.left).is_some(),
+        };
+        if has_left {
+            let (removed, shallowed) = self.with_child(tree, Less, |s, left| s.remove_min(left));
+            self.node_at(tree).size -= 1;
+            return (removed, shallowed && self.left_shallowed(tree));
+        }
+        let node = self.free(mem::take(tree)).unwrap();
+        *tree = node.right;
+        (Some(node.value), true)
+    }
+
+    fn remove_max(&mut self, tree: &mut Self::Link) -> (Option<T>, bool) {
+        // returns: (removed, shallowed)
+        let has_right = match self.node(tree) {
+            None => return (None, false),
+            Some(node) => self.node(&node.right).is_some(),
+        };
+        if has_right {
+            let (removed, shallowed) =
+                self.with_child(tree, Greater, |s, right| s.remove_max(right));
+            self.node_at(tree).size -= 1;
+            return (removed, shallowed && self.right_shallowed(tree));
+        }
+        let node = self.free(mem::take(tree)).unwrap();
+        *tree = node.left;
+        (Some(node.value), true)
+    }
+
+    fn remove_nth(&mut self, tree: &mut Self::Link, k: usize) -> (Option<T>, bool) {
+        // `k` must be less than the size of `tree`
+        // returns: (removed, shallowed)
+        let l = match self.node(tree) {
+            None => return (None, false),
+            Some(node) => self.len(&node.left),
+        };
+        match k.cmp(&l) {
+            Less => {
+                let (removed, shallowed) =
+                    self.with_child(tree, Less, |s, left| s.remove_nth(left, k));
+                self.node_at(tree).size -= 1;
+                (removed, shallowed && self.left_shallowed(tree))
+            }
+            Greater => {
+                let (removed, shallowed) =
+                    self.with_child(tree, Greater, |s, right| s.remove_nth(right, k - l - 1));
+                self.node_at(tree).size -= 1;
+                (removed, shallowed && self.right_shallowed(tree))
+            }
+            Equal => self.remove_root(tree),
+        }
+    }
+
+    fn remove_by<F>(&mut self, tree: &mut Self::Link, f: &F) -> (Option<T>, bool)
+    where
+        F: Fn(&T) -> Ordering,
+    {
+        // `f` compares the target against a node's value
+        // returns: (removed, shallowed)
+        let ord = match self.node(tree) {
+            None => return (None, false),
+            Some(node) => f(&node.value),
+        };
+        match ord {
+            Less => {
+                let (removed, shallowed) =
+                    self.with_child(tree, Less, |s, left| s.remove_by(left, f));
+                if removed.is_some() {
+                    self.node_at(tree).size -= 1;
+                }
+                (removed, shallowed && self.left_shallowed(tree))
+            }
+            Greater => {
+                let (removed, shallowed) =
+                    self.with_child(tree, Greater, |s, right| s.remove_by(right, f));
+                if removed.is_some() {
+                    self.node_at(tree).size -= 1;
+                }
+                (removed, shallowed && self.right_shallowed(tree))
+            }
+            Equal => self.remove_root(tree),
+        }
+    }
+
+    fn remove_root(&mut self, tree: &mut Self::Link) -> (Option<T>, bool) {
+        // removes the root value of a non-empty `tree`, replacing it with its successor
+        // returns: (removed, shallowed)
+        if self.node(self.child(tree, Greater)).is_some() {
+            let (next, shallowed) = self.with_child(tree, Greater, |s, right| s.remove_min(right));
+            let node = self.node_at(tree);
+            let removed = mem::replace(&mut node.value, next.unwrap());
+            node.size -= 1;
+            return (Some(removed), shallowed && self.right_shallowed(tree));
+        }
+        let node = self.free(mem::take(tree)).unwrap();
+        *tree = node.left;
+        (Some(node.value), true)
+    }
+
+    fn left_shallowed(&mut self, tree: &mut Self::Link) -> bool {
+        // called after the left subtree lost one level; returns whether `tree` did too
+        let node = self.node_at(tree);
+        node.balance_factor += 1;
+        match node.balance_factor {
+            0 => true,
+            1 => false,
+            _ => {
+                self.balance(tree);
+                self.factor(tree) == 0
+            }
+        }
+    }
+
+    fn right_shallowed(&mut self, tree: &mut Self::Link) -> bool {
+        // called after the right subtree lost one level; returns whether `tree` did too
+        let node = self.node_at(tree);
+        node.balance_factor -= 1;
+        match node.balance_factor {
+            0 => true,
+            -1 => false,
+            _ => {
+                self.balance(tree);
+                self.factor(tree) == 0
+            }
+        }
+    }
+
+    fn left_deepened(&mut self, tree: &mut Self::Link) -> bool {
+        // called after the left subtree gained one level; returns whether `tree` did too
+        let node = self.node_at(tree);
+        node.balance_factor -= 1;
+        match node.balance_factor {
+            0 => false,
+            -1 => true,
+            _ => {
+                self.balance(tree);
+                false
+            }
+        }
+    }
+
+    fn right_deepened(&mut self, tree: &mut Self::Link) -> bool {
+        // called after the right subtree gained one level; returns whether `tree` did too
+        let node = self.node_at(tree);
+        node.balance_factor += 1;
+        match node.balance_factor {
+            0 => false,
+            1 => true,
+            _ => {
+                self.balance(tree);
+                false
+            }
+        }
+    }
+
+    fn join_with(
+        &mut self,
+        left: Self::Link,
+        hl: usize,
+        value: T,
+        right: Self::Link,
+        hr: usize,
+    ) -> (Self::Link, usize) {
+        // every value in `left` < `value` < every value in `right`; `hl` and `hr` are their
+        // heights, passed in so that joining along a split path stays O(log n) overall
+        // returns: (joined, height)
+        if hl > hr + 1 {
+            let mut tree = left;
+            let deepened = self.join_right(&mut tree, hl, value, right, hr);
+            (tree, hl + deepened as usize)
+        } else if hr > hl + 1 {
+            let mut tree = right;
+            let deepened = self.join_left(&mut tree, hr, value, left, hl);
+            (tree, hr + deepened as usize)
+        } else {
+            let node = Node {
+                value,
+                size: self.len(&left) + self.len(&right) + 1,
+                left,
+                right,
+                balance_factor: hr as i8 - hl as i8,
+            };
+            (self.alloc(node), hl.max(hr) + 1)
+        }
+    }
+
+    fn join_right(
+        &mut self,
+        tree: &mut Self::Link,
+        h: usize,
+        value: T,
+        right: Self::Link,
+        hr: usize,
+    ) -> bool {
+        // attaches `value` and `right` along the right spine of `tree` (of height `h`)
+        // returns: deepened
+        let rh = self.node_at(tree).child_heights(h).1;
+        if rh <= hr + 1 {
+            let left = mem::take(&mut self.node_at(tree).right);
+            let joined = self.join_with(left, rh, value, right, hr).0;
+            self.node_at(tree).right = joined;
+        } else if !self.with_child(tree, Greater, |s, t| s.join_right(t, rh, value, right, hr)) {
+            self.update_size(tree);
+            return false;
+        }
+        self.update_size(tree);
+        self.right_deepened(tree)
+    }
+
+    fn join_left(
+        &mut self,
+        tree: &mut Self::Link,
+        h: usize,
+        value: T,
+        left: Self::Link,
+        hl: usize,
+    ) -> bool {
+        // attaches `left` and `value` along the left spine of `tree` (of height `h`)
+        // returns: deepened
+        let lh = self.node_at(tree).child_heights(h).0;
+        if lh <= hl + 1 {
+            let right = mem::take(&mut self.node_at(tree).left);
+            let joined = self.join_with(left, hl, value, right, lh).0;
+            self.node_at(tree).left = joined;
+        } else if !self.with_child(tree, Less, |s, t| s.join_left(t, lh, value, left, hl)) {
+            self.update_size(tree);
+            return false;
+        }
+        self.update_size(tree);
+        self.left_deepened(tree)
+    }
+
+    fn join(&mut self, left: Self::Link, mut right: Self::Link) -> Self::Link {
+        // every value in `left` < every value in `right`
+        let (hl, hr) = (self.height(&left), self.height(&right));
+        match self.remove_min(&mut right) {
+            (None, _) => left,
+            (Some(value), shallowed) => {
+                self.join_with(left, hl, value, right, hr - shallowed as usize)
+                    .0
+            }
+        }
+    }
+
+    fn split<Q>(&mut self, tree: Self::Link, key: &Q) -> (Self::Link, Self::Link)
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // returns: (values < key, values >= key)
+        let h = self.height(&tree);
+        let ((l, _), (r, _)) = self.split_at_height(tree, h, key);
+        (l, r)
+    }
+
+    fn split_at_height<Q>(
+        &mut self,
+        tree: Self::Link,
+        h: usize,
+        key: &Q,
+    ) -> ((Self::Link, usize), (Self::Link, usize))
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // `h` is the height of `tree`
+        // returns: (values < key, values >= key), each with its height
+        match self.free(tree) {
+            None => Default::default(),
+            Some(node) => {
+                let (hl, hr) = node.child_heights(h);
+                let Node {
+                    value, left, right, ..
+                } = node;
+                if key <= value.borrow() {
+                    let (l, (r, h)) = self.split_at_height(left, hl, key);
+                    (l, self.join_with(r, h, value, right, hr))
+                } else {
+                    let ((l, h), r) = self.split_at_height(right, hr, key);
+                    (self.join_with(left, hl, value, l, h), r)
+                }
+            }
+        }
+    }
+
+    fn split_rank(&mut self, tree: Self::Link, k: usize) -> (Self::Link, Self::Link) {
+        // returns: (the `k` smallest values, the rest)
+        let h = self.height(&tree);
+        let ((a, _), (b, _)) = self.split_rank_at_height(tree, h, k);
+        (a, b)
+    }
+
+    fn split_rank_at_height(
+        &mut self,
+        tree: Self::Link,
+        h: usize,
+        k: usize,
+    ) -> ((Self::Link, usize), (Self::Link, usize)) {
+        // `h` is the height of `tree`
+        // returns: (the `k` smallest values, the rest), each with its height
+        match self.free(tree) {
+            None => Default::default(),
+            Some(node) => {
+                let (hl, hr) = node.child_heights(h);
+                let Node {
+                    value, left, right, ..
+                } = node;
+                let l = self.len(&left);
+                if k <= l {
+                    let (a, (b, h)) = self.split_rank_at_height(left, hl, k);
+                    (a, self.join_with(b, h, value, right, hr))
+                } else {
+                    let ((a, h), b) = self.split_rank_at_height(right, hr, k - l - 1);
+                    (self.join_with(left, hl, value, a, h), b)
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, tree: &mut Self::Link, other: Self::Link)
+    where
+        T: Ord,
+    {
+        let left = mem::take(tree);
+        if self.node(&left).is_none() {
+            *tree = other;
+            return;
+        }
+        if self.node(&other).is_none() {
+            *tree = left;
+            return;
+        }
+        if self.last(&left) < self.first(&other) {
+            *tree = self.join(left, other);
+            return;
+        }
+        if self.last(&other) < self.first(&left) {
+            *tree = self.join(other, left);
+            return;
+        }
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        self.drain_sorted(left, &mut a);
+        self.drain_sorted(other, &mut b);
+        let mut a = a.into_iter().peekable();
+        let mut b = b.into_iter().peekable();
+        let mut values = Vec::with_capacity(a.len() + b.len());
+        loop {
+            let v = match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(_), None) => a.next(),
+                (None, Some(_)) => b.next(),
+                (Some(x), Some(y)) => match x.cmp(y) {
+                    Less => a.next(),
+                    Greater => b.next(),
+                    Equal => {
+                        b.next();
+                        a.next()
+                    }
+                },
+            };
+            values.extend(v);
+        }
+        *tree = self.build(values.into_iter());
+    }
+
+    fn retain<F: FnMut(&T) -> bool>(&mut self, tree: &mut Self::Link, mut f: F) {
+        let mut values = Vec::with_capacity(self.len(tree));
+        self.drain_sorted(mem::take(tree), &mut values);
+        values.retain(|v| f(v));
+        *tree = self.build(values.into_iter());
+    }
+
+    fn find_by<'a, F>(&'a self, mut tree: &'a Self::Link, f: &F) -> Option<&'a T>
+    where
+        F: Fn(&T) -> Ordering,
+    {
+        while let Some(node) = self.node(tree) {
+            match f(&node.value) {
+                Equal => return Some(&node.value),
+                ord => tree = node.child(ord),
+            }
+        }
+        None
+    }
+
+    fn get<'a, Q>(&'a self, tree: &'a Self::Link, key: &Q) -> Option<&'a T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.find_by(tree, &|v: &T| key.cmp(v.borrow()))
+    }
+
+    fn floor<'a, Q>(&'a self, tree: &'a Self::Link, key: &Q) -> Option<&'a T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // largest value <= key
+        let node = self.node(tree)?;
+        match key.cmp(node.value.borrow()) {
+            Less => self.floor(&node.left, key),
+            Equal => Some(&node.value),
+            Greater => self.floor(&node.right, key).or(Some(&node.value)),
+        }
+    }
+
+    fn ceiling<'a, Q>(&'a self, tree: &'a Self::Link, key: &Q) -> Option<&'a T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // smallest value >= key
+        let node = self.node(tree)?;
+        match key.cmp(node.value.borrow()) {
+            Less => self.ceiling(&node.left, key).or(Some(&node.value)),
+            Equal => Some(&node.value),
+            Greater => self.ceiling(&node.right, key),
+        }
+    }
+
+    fn predecessor<'a, Q>(&'a self, tree: &'a Self::Link, key: &Q) -> Option<&'a T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // largest value < key
+        let node = self.node(tree)?;
+        match key.cmp(node.value.borrow()) {
+            Greater => self.predecessor(&node.right, key).or(Some(&node.value)),
+            _ => self.predecessor(&node.left, key),
+        }
+    }
+
+    fn successor<'a, Q>(&'a self, tree: &'a Self::Link, key: &Q) -> Option<&'a T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        // smallest value > key
+        let node = self.node(tree)?;
+        match key.cmp(node.value.borrow()) {
+            Less => self.successor(&node.left, key).or(Some(&node.value)),
+            _ => self.successor(&node.right, key),
+        }
+    }
+
+    fn rank<Q>(&self, tree: &Self::Link, key: &Q) -> usize
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.count_prefix(tree, &|v: &T| v.borrow() < key)
+    }
+
+    /// Counts the elements satisfying `pred`, which must hold on a prefix of the sorted order.
+    fn count_prefix<'a, P>(&'a self, mut tree: &'a Self::Link, pred: &P) -> usize
+    where
+        T: 'a,
+        P: Fn(&T) -> bool,
+    {
+        let mut count = 0;
+        while let Some(node) = self.node(tree) {
+            if pred(&node.value) {
+                count += self.len(&node.left) + 1;
+                tree = &node.right;
+            } else {
+                tree = &node.left;
+            }
+        }
+        count
+    }
+
+    fn select<'a>(&'a self, tree: &'a Self::Link, k: usize) -> Option<&'a T> {
+        let node = self.node(tree)?;
+        let l = self.len(&node.left);
+        match k.cmp(&l) {
+            Less => self.select(&node.left, k),
+            Equal => Some(&node.value),
+            Greater => self.select(&node.right, k - l - 1),
+        }
+    }
+
+    fn first<'a>(&'a self, mut tree: &'a Self::Link) -> Option<&'a T> {
+        let mut first = None;
+        while let Some(node) = self.node(tree) {
+            first = Some(&node.value);
+            tree = &node.left;
+        }
+        first
+    }
+
+    fn last<'a>(&'a self, mut tree: &'a Self::Link) -> Option<&'a T> {
+        let mut last = None;
+        while let Some(node) = self.node(tree) {
+            last = Some(&node.value);
+            tree = &node.right;
+        }
+        last
+    }
+
+    fn checked_height(&self, tree: &Self::Link) -> Option<usize> {
+        // returns: the height, or `None` if a balance factor or size is wrong
+        let node = match self.node(tree) {
+            None => return Some(0),
+            Some(node) => node,
+        };
+        let l = self.checked_height(&node.left)?;
+        let r = self.checked_height(&node.right)?;
+        let bf = r as i64 - l as i64;
+        if bf != node.balance_factor as i64
+            || bf.abs() > 1
+            || node.size != 1 + self.len(&node.left) + self.len(&node.right)
+        {
+            return None;
+        }
+        Some(core::cmp::max(l, r) + 1)
+    }
+
+    fn rotate_right(&mut self, tree: &mut Self::Link) {
+        let mut v = mem::take(tree);
+        let mut left = mem::take(&mut self.node_at(&mut v).left);
+        let left_right = mem::take(&mut self.node_at(&mut left).right);
+        self.node_at(&mut v).left = left_right;
+        self.update_size(&mut v);
+        self.node_at(&mut left).right = v;
+        self.update_size(&mut left);
+        *tree = left;
+    }
+
+    fn rotate_left(&mut self, tree: &mut Self::Link) {
+        let mut v = mem::take(tree);
+        let mut right = mem::take(&mut self.node_at(&mut v).right);
+        let right_left = mem::take(&mut self.node_at(&mut right).left);
+        self.node_at(&mut v).right = right_left;
+        self.update_size(&mut v);
+        self.node_at(&mut right).left = v;
+        self.update_size(&mut right);
+        *tree = right;
+    }
+
+    fn balance(&mut self, tree: &mut Self::Link) {
+        let bf = match self.node(tree) {
+            None => return,
+            Some(node) => node.balance_factor,
+        };
+        match bf {
+            -2 => {
+                let lf = self.factor(self.child(tree, Less));
+                if lf == -1 || lf == 0 {
+                    let (a, b) = if lf == -1 { (0, 0) } else { (-1, 1) };
+                    self.rotate_right(tree);
+                    self.set_child_factor(tree, Greater, a);
+                    self.node_at(tree).balance_factor = b;
+                } else if lf == 1 {
+                    let (a, b) = match self.factor(self.child(self.child(tree, Less), Greater)) {
+                        -1 => (1, 0),
+                        0 => (0, 0),
+                        1 => (0, -1),
+                        _ => unreachable!(),
+                    };
+                    self.with_child(tree, Less, |s, left| s.rotate_left(left));
+                    self.rotate_right(tree);
+                    self.set_child_factor(tree, Greater, a);
+                    self.set_child_factor(tree, Less, b);
+                    self.node_at(tree).balance_factor = 0;
+                } else {
+                    unreachable!()
+                }
+            }
+            2 => {
+                let lf = self.factor(self.child(tree, Greater));
+                if lf == 1 || lf == 0 {
+                    let (a, b) = if lf == 1 { (0, 0) } else { (1, -1) };
+                    self.rotate_left(tree);
+                    self.set_child_factor(tree, Less, a);
+                    self.node_at(tree).balance_factor = b;
+                } else if lf == -1 {
+                    let (a, b) = match self.factor(self.child(self.child(tree, Greater), Less)) {
+                        1 => (-1, 0),
+                        0 => (0, 0),
+                        -1 => (0, 1),
+                        _ => unreachable!(),
+                    };
+                    self.with_child(tree, Greater, |s, right| s.rotate_right(right));
+                    self.rotate_left(tree);
+                    self.set_child_factor(tree, Less, a);
+                    self.set_child_factor(tree, Greater, b);
+                    self.node_at(tree).balance_factor = 0;
+                } else {
+                    unreachable!()
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn set_child_factor(&mut self, tree: &mut Self::Link, ord: Ordering, balance_factor: i8) {
+        self.with_child(tree, ord, |s, child| {
+            s.node_at(child).balance_factor = balance_factor
+        });
+    }
+}
+
+impl<T, L> Node<T, L> {
+    fn child_heights(&self, h: usize) -> (usize, usize) {
+        // the heights of the subtrees of a node at height `h`
+        let hl = if self.balance_factor > 0 {
+            h - 2
+        } else {
+            h - 1
+        };
+        let hr = if self.balance_factor < 0 {
+            h - 2
+        } else {
+            h - 1
+        };
+        (hl, hr)
+    }
+
+    fn child(&self, ord: Ordering) -> &L {
+        if ord == Less {
+            &self.left
+        } else {
+            &self.right
+        }
+    }
+
+    fn child_mut(&mut self, ord: Ordering) -> &mut L {
+        if ord == Less {
+            &mut self.left
+        } else {
+            &mut self.right
+        }
+    }
+}
+
+/// The storage of [`AVLTree`]: every node sits in its own `Box`, owned by its parent's link.
+pub struct Boxed;
+
+impl<T> Store<T> for Boxed {
+    type Link = AVLTree<T>;
+
+    fn node<'a>(&'a self, tree: &'a AVLTree<T>) -> Option<&'a Node<T>> {
+        tree.as_node()
+    }
+
+    fn node_mut<'a>(&'a mut self, tree: &'a mut AVLTree<T>) -> Option<&'a mut Node<T>> {
+        match *tree {
+            AVLTree::Empty => None,
+            AVLTree::NonEmpty(ref mut node) => Some(node),
+        }
+    }
+
+    fn alloc(&mut self, node: Node<T>) -> AVLTree<T> {
+        AVLTree::NonEmpty(Box::new(node))
+    }
+
+    fn free(&mut self, tree: AVLTree<T>) -> Option<Node<T>> {
+        tree.into_node().map(|node| *node)
+    }
+}
@@ -0,0 +1,56 @@
+//! Allocations made while dropping a tree, counted by a global allocator that only this
+//! test binary installs.
+use avltree::AVLTree;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::mem;
+
+thread_local! {
+    // (number of allocations, largest allocation in bytes)
+    static ALLOCATIONS: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|c| {
+            let (n, largest) = c.get();
+            c.set((n + 1, largest.max(layout.size())));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn drop_allocations<T>(tree: AVLTree<T>) -> (usize, usize) {
+    ALLOCATIONS.with(|c| c.set((0, 0)));
+    drop(tree);
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn drop_leaf() {
+    let mut tree = AVLTree::new();
+    tree.insert(0usize);
+    assert_eq!(drop_allocations(tree), (0, 0));
+}
+
+#[test]
+fn drop_worklist_bounded_by_height() {
+    for &n in &[2usize, 10, 1000, 100_000] {
+        let tree: AVLTree<_> = (0..n).collect();
+        let height = tree.height();
+        let (_, largest) = drop_allocations(tree);
+        // the worklist holds at most one pending sibling per level plus both children of the
+        // deepest node, and growing a `Vec` at most doubles its capacity
+        let slots = 2 * (height + 1);
+        assert!(largest <= slots.max(4) * mem::size_of::<AVLTree<usize>>());
+    }
+}